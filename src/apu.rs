@@ -0,0 +1,974 @@
+// Reference Manual - https://gbdev.io/pandocs/Audio.html
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const CPU_FREQUENCY: u32 = 4_194_304;
+
+const NR10: u16 = 0xFF10;
+const NR11: u16 = 0xFF11;
+const NR14: u16 = 0xFF14;
+const NR21: u16 = 0xFF16;
+const NR24: u16 = 0xFF19;
+const NR30: u16 = 0xFF1A;
+const NR31: u16 = 0xFF1B;
+const NR34: u16 = 0xFF1E;
+const NR41: u16 = 0xFF20;
+const NR44: u16 = 0xFF23;
+const NR50: u16 = 0xFF24;
+const NR51: u16 = 0xFF25;
+const NR52: u16 = 0xFF26;
+const WAVE_RAM_START: u16 = 0xFF30;
+const WAVE_RAM_END: u16 = 0xFF3F;
+
+// Bits that always read back as 1, indexed from NR10. Write-only bits read as 1 too
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // unused, NR21-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // unused, NR41-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+];
+
+const NRX4_TRIGGER: u8 = 0b1000_0000;
+const NRX4_LENGTH_ENABLE: u8 = 0b0100_0000;
+const NR52_POWER: u8 = 0b1000_0000;
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Samples nobody drains are dropped beyond this many seconds, so headless runs don't grow forever
+const MAX_BUFFERED_SECONDS: usize = 1;
+
+// The DACs feed the output through a capacitor that slowly removes any DC offset
+const CAPACITOR_CHARGE_PER_CYCLE: f64 = 0.999958;
+
+// Converts a 4-bit channel output to an analog level between -1 and 1
+fn dac(enabled: bool, digital: u8) -> f32 {
+    if enabled {
+        digital as f32 / 7.5 - 1.0
+    } else {
+        0.0
+    }
+}
+
+struct LengthCounter {
+    enabled: bool,
+    counter: u16,
+    max: u16,
+}
+
+impl LengthCounter {
+    fn new(max: u16) -> Self {
+        Self {
+            enabled: false,
+            counter: 0,
+            max,
+        }
+    }
+
+    fn load(&mut self, value: u8) {
+        self.counter = self.max - value as u16;
+    }
+
+    // Returns true when the counter runs out and the channel should turn off
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    // NRx4 writes clock the counter early when the sequencer's next step won't, returns true if
+    // that runs it out
+    fn write_control(&mut self, value: u8, next_step_skips_length: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = value & NRX4_LENGTH_ENABLE != 0;
+        let trigger = value & NRX4_TRIGGER != 0;
+
+        let mut expired = false;
+        if next_step_skips_length && !was_enabled && self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            expired = self.counter == 0 && !trigger;
+        }
+        if trigger && self.counter == 0 {
+            self.counter = if next_step_skips_length && self.enabled {
+                self.max - 1
+            } else {
+                self.max
+            };
+        }
+        expired
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u16(self.counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.enabled = r.bool()?;
+        self.counter = r.u16()?;
+        Ok(())
+    }
+}
+
+struct Envelope {
+    initial_volume: u8,
+    increase: bool,
+    pace: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn new() -> Self {
+        Self {
+            initial_volume: 0,
+            increase: false,
+            pace: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    fn register(&self) -> u8 {
+        (self.initial_volume << 4) | ((self.increase as u8) << 3) | self.pace
+    }
+
+    fn write(&mut self, value: u8) {
+        self.initial_volume = value >> 4;
+        self.increase = value & 0b0000_1000 != 0;
+        self.pace = value & 0b0000_0111;
+    }
+
+    // The DAC is off when NRx2 would have the channel start silent and stay that way
+    fn dac_enabled(&self) -> bool {
+        self.initial_volume != 0 || self.increase
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial_volume;
+        self.timer = self.pace;
+    }
+
+    fn clock(&mut self) {
+        if self.pace == 0 {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.pace;
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.register());
+        w.u8(self.volume);
+        w.u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.write(r.u8()?);
+        self.volume = r.u8()?;
+        self.timer = r.u8()?;
+        Ok(())
+    }
+}
+
+enum SweepOutcome {
+    Unchanged,
+    Period(u16),
+    Overflow,
+}
+
+struct Sweep {
+    pace: u8,
+    decrease: bool,
+    step: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    // Switching to increase after a decrease calculation disables the channel
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn new() -> Self {
+        Self {
+            pace: 0,
+            decrease: false,
+            step: 0,
+            timer: 0,
+            enabled: false,
+            shadow: 0,
+            negate_used: false,
+        }
+    }
+
+    fn register(&self) -> u8 {
+        (self.pace << 4) | ((self.decrease as u8) << 3) | self.step
+    }
+
+    // Returns false if the write disables the channel
+    fn write(&mut self, value: u8) -> bool {
+        let was_decrease = self.decrease;
+        self.pace = (value >> 4) & 0b0111;
+        self.decrease = value & 0b0000_1000 != 0;
+        self.step = value & 0b0000_0111;
+        !(was_decrease && !self.decrease && self.negate_used)
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = if self.pace == 0 { 8 } else { self.pace };
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta = self.shadow >> self.step;
+        if self.decrease {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+
+    // Returns false if the first overflow check disables the channel
+    fn trigger(&mut self, period: u16) -> bool {
+        self.shadow = period;
+        self.reload_timer();
+        self.enabled = self.pace != 0 || self.step != 0;
+        self.negate_used = false;
+        self.step == 0 || self.calculate() <= 0x7FF
+    }
+
+    fn clock(&mut self) -> SweepOutcome {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer != 0 {
+            return SweepOutcome::Unchanged;
+        }
+        self.reload_timer();
+        if !self.enabled || self.pace == 0 {
+            return SweepOutcome::Unchanged;
+        }
+
+        let period = self.calculate();
+        if period > 0x7FF {
+            return SweepOutcome::Overflow;
+        }
+        if self.step == 0 {
+            return SweepOutcome::Unchanged;
+        }
+        self.shadow = period;
+        // The new period is checked again straight away but not used
+        if self.calculate() > 0x7FF {
+            return SweepOutcome::Overflow;
+        }
+        SweepOutcome::Period(period)
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.register());
+        w.u8(self.timer);
+        w.bool(self.enabled);
+        w.u16(self.shadow);
+        w.bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        let register = r.u8()?;
+        self.pace = (register >> 4) & 0b0111;
+        self.decrease = register & 0b0000_1000 != 0;
+        self.step = register & 0b0000_0111;
+        self.timer = r.u8()?;
+        self.enabled = r.bool()?;
+        self.shadow = r.u16()?;
+        self.negate_used = r.bool()?;
+        Ok(())
+    }
+}
+
+// Channels 1 and 2, only channel 1 has a sweep unit
+struct PulseChannel {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    period: u16,
+    timer: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+    sweep: Option<Sweep>,
+}
+
+impl PulseChannel {
+    fn new(has_sweep: bool) -> Self {
+        Self {
+            enabled: false,
+            duty: 0,
+            duty_step: 0,
+            period: 0,
+            timer: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            sweep: has_sweep.then(Sweep::new),
+        }
+    }
+
+    fn period_cycles(&self) -> u16 {
+        (2048 - self.period) * 4
+    }
+
+    fn tick(&mut self, mut cycles: u16) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period_cycles();
+            self.duty_step = (self.duty_step + 1) % 8;
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        DUTY_PATTERNS[self.duty as usize][self.duty_step as usize] * self.envelope.volume
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period_cycles();
+        self.envelope.trigger();
+        if let Some(sweep) = &mut self.sweep
+            && !sweep.trigger(self.period)
+        {
+            self.enabled = false;
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        if let Some(sweep) = &mut self.sweep {
+            match sweep.clock() {
+                SweepOutcome::Unchanged => (),
+                SweepOutcome::Period(period) => self.period = period,
+                SweepOutcome::Overflow => self.enabled = false,
+            }
+        }
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => self.sweep.as_ref().map_or(0, Sweep::register),
+            1 => self.duty << 6,
+            2 => self.envelope.register(),
+            4 => (self.length.enabled as u8) << 6,
+            _ => 0,
+        }
+    }
+
+    // `reg` is the offset from NRx0
+    fn write(&mut self, value: u8, reg: u16, next_step_skips_length: bool) {
+        match reg {
+            0 => {
+                if let Some(sweep) = &mut self.sweep
+                    && !sweep.write(value)
+                {
+                    self.enabled = false;
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.period = (self.period & 0x700) | value as u16,
+            4 => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0b111) << 8);
+                if self.length.write_control(value, next_step_skips_length) {
+                    self.enabled = false;
+                }
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u8(self.duty);
+        w.u8(self.duty_step);
+        w.u16(self.period);
+        w.u16(self.timer);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.enabled = r.bool()?;
+        self.duty = r.u8()?;
+        self.duty_step = r.u8()?;
+        self.period = r.u16()?;
+        self.timer = r.u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        match &mut self.sweep {
+            Some(sweep) => sweep.load_state(r),
+            None => Ok(()),
+        }
+    }
+}
+
+// Channel 3 plays back 32 4-bit samples from wave RAM
+struct WaveChannel {
+    enabled: bool,
+    dac_enabled: bool,
+    output_level: u8,
+    period: u16,
+    timer: u16,
+    position: u8,
+    sample: u8,
+    length: LengthCounter,
+    ram: [u8; 16],
+}
+
+impl WaveChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            dac_enabled: false,
+            output_level: 0,
+            period: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            length: LengthCounter::new(256),
+            ram: [0; 16],
+        }
+    }
+
+    fn period_cycles(&self) -> u16 {
+        (2048 - self.period) * 2
+    }
+
+    fn tick(&mut self, mut cycles: u16) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period_cycles();
+            self.position = (self.position + 1) % 32;
+            let byte = self.ram[self.position as usize / 2];
+            // High nibble plays first
+            self.sample = if self.position.is_multiple_of(2) {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || self.output_level == 0 {
+            return 0;
+        }
+        self.sample >> (self.output_level - 1)
+    }
+
+    // The sample buffer isn't refilled, so the first sample played is the last one latched
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = self.period_cycles();
+        self.position = 0;
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => (self.dac_enabled as u8) << 7,
+            2 => self.output_level << 5,
+            4 => (self.length.enabled as u8) << 6,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, value: u8, reg: u16, next_step_skips_length: bool) {
+        match reg {
+            0 => {
+                self.dac_enabled = value & 0b1000_0000 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.output_level = (value >> 5) & 0b11,
+            3 => self.period = (self.period & 0x700) | value as u16,
+            4 => {
+                self.period = (self.period & 0xFF) | ((value as u16 & 0b111) << 8);
+                if self.length.write_control(value, next_step_skips_length) {
+                    self.enabled = false;
+                }
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.bool(self.dac_enabled);
+        w.u8(self.output_level);
+        w.u16(self.period);
+        w.u16(self.timer);
+        w.u8(self.position);
+        w.u8(self.sample);
+        self.length.save_state(w);
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.enabled = r.bool()?;
+        self.dac_enabled = r.bool()?;
+        self.output_level = r.u8()?;
+        self.period = r.u16()?;
+        self.timer = r.u16()?;
+        self.position = r.u8()?;
+        self.sample = r.u8()?;
+        self.length.load_state(r)?;
+        r.fill(&mut self.ram)
+    }
+}
+
+// Channel 4 outputs the low bit of a linear feedback shift register
+struct NoiseChannel {
+    enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divider: u8,
+    timer: u32,
+    lfsr: u16,
+    length: LengthCounter,
+    envelope: Envelope,
+}
+
+impl NoiseChannel {
+    fn new() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divider: 0,
+            timer: 0,
+            lfsr: 0,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    fn register(&self) -> u8 {
+        (self.clock_shift << 4) | ((self.short_mode as u8) << 3) | self.divider
+    }
+
+    fn period_cycles(&self) -> u32 {
+        NOISE_DIVISORS[self.divider as usize] << self.clock_shift
+    }
+
+    fn tick(&mut self, mut cycles: u32) {
+        // Shifts 14 and 15 stop the LFSR from being clocked at all
+        if self.clock_shift >= 14 {
+            return;
+        }
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period_cycles();
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 1 != 0 {
+            return 0;
+        }
+        self.envelope.volume
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period_cycles();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        match reg {
+            2 => self.envelope.register(),
+            3 => self.register(),
+            4 => (self.length.enabled as u8) << 6,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, value: u8, reg: u16, next_step_skips_length: bool) {
+        match reg {
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.envelope.write(value);
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => {
+                self.clock_shift = value >> 4;
+                self.short_mode = value & 0b0000_1000 != 0;
+                self.divider = value & 0b0000_0111;
+            }
+            4 => {
+                if self.length.write_control(value, next_step_skips_length) {
+                    self.enabled = false;
+                }
+                if value & NRX4_TRIGGER != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u8(self.register());
+        w.u32(self.timer);
+        w.u16(self.lfsr);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.enabled = r.bool()?;
+        self.write(r.u8()?, 3, false);
+        self.timer = r.u32()?;
+        self.lfsr = r.u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)
+    }
+}
+
+pub struct APU {
+    powered: bool,
+    nr50: u8,
+    nr51: u8,
+    ch1: PulseChannel,
+    ch2: PulseChannel,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+    // Next step of the 512 Hz frame sequencer, clocked by DIV
+    frame_step: u8,
+
+    sample_rate: u32,
+    // Output is averaged over each sample period, then high-pass filtered
+    sample_phase: u32,
+    mix_sum: (f32, f32),
+    mix_cycles: u32,
+    capacitor: (f32, f32),
+    charge_factor: f32,
+    samples: Vec<f32>,
+}
+
+impl APU {
+    pub fn initialize(sample_rate: u32) -> Self {
+        let mut apu = Self {
+            powered: true,
+            nr50: 0x77,
+            nr51: 0xF3,
+            ch1: PulseChannel::new(true),
+            ch2: PulseChannel::new(false),
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+            frame_step: 0,
+            sample_rate: 0,
+            sample_phase: 0,
+            mix_sum: (0.0, 0.0),
+            mix_cycles: 0,
+            capacitor: (0.0, 0.0),
+            charge_factor: 0.0,
+            samples: Vec::new(),
+        };
+        // Post-boot state, the boot ROM leaves channel 1 configured from its chime
+        apu.ch1.write(0xBF, 1, false);
+        apu.ch1.write(0xF3, 2, false);
+        apu.ch1.enabled = true;
+        apu.set_sample_rate(sample_rate);
+        apu
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate.max(1);
+        let cycles_per_sample = CPU_FREQUENCY as f64 / self.sample_rate as f64;
+        self.charge_factor = CAPACITOR_CHARGE_PER_CYCLE.powf(cycles_per_sample) as f32;
+    }
+
+    // Interleaved left/right samples produced since the last call
+    pub fn drain_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    fn next_step_skips_length(&self) -> bool {
+        !self.frame_step.is_multiple_of(2)
+    }
+
+    // Called on each falling edge of DIV bit 4
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+
+        if self.frame_step.is_multiple_of(2) {
+            if self.ch1.length.clock() {
+                self.ch1.enabled = false;
+            }
+            if self.ch2.length.clock() {
+                self.ch2.enabled = false;
+            }
+            if self.ch3.length.clock() {
+                self.ch3.enabled = false;
+            }
+            if self.ch4.length.clock() {
+                self.ch4.enabled = false;
+            }
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.ch1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.ch1.envelope.clock();
+            self.ch2.envelope.clock();
+            self.ch4.envelope.clock();
+        }
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    fn mix(&self) -> (f32, f32) {
+        let outputs = [
+            dac(self.ch1.envelope.dac_enabled(), self.ch1.output()),
+            dac(self.ch2.envelope.dac_enabled(), self.ch2.output()),
+            dac(self.ch3.dac_enabled, self.ch3.output()),
+            dac(self.ch4.envelope.dac_enabled(), self.ch4.output()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for (i, output) in outputs.into_iter().enumerate() {
+            if self.nr51 & (0x10 << i) != 0 {
+                left += output;
+            }
+            if self.nr51 & (0x01 << i) != 0 {
+                right += output;
+            }
+        }
+
+        let left_volume = ((self.nr50 >> 4) & 0b111) as f32 + 1.0;
+        let right_volume = (self.nr50 & 0b111) as f32 + 1.0;
+        (left * left_volume / 32.0, right * right_volume / 32.0)
+    }
+
+    fn push_sample(&mut self) {
+        let cycles = self.mix_cycles.max(1) as f32;
+        let left = self.mix_sum.0 / cycles;
+        let right = self.mix_sum.1 / cycles;
+        self.mix_sum = (0.0, 0.0);
+        self.mix_cycles = 0;
+
+        let out_left = left - self.capacitor.0;
+        let out_right = right - self.capacitor.1;
+        self.capacitor.0 = left - out_left * self.charge_factor;
+        self.capacitor.1 = right - out_right * self.charge_factor;
+
+        let max_samples = 2 * self.sample_rate as usize * MAX_BUFFERED_SECONDS;
+        if self.samples.len() >= max_samples {
+            self.samples.drain(..max_samples / 2);
+        }
+        self.samples.push(out_left);
+        self.samples.push(out_right);
+    }
+
+    pub fn step(&mut self, cycles: u8) {
+        // Channels advance an M-cycle at a time, which every period is a multiple of
+        for _ in 0..cycles / 4 {
+            if self.powered {
+                self.ch1.tick(4);
+                self.ch2.tick(4);
+                self.ch3.tick(4);
+                self.ch4.tick(4);
+            }
+
+            let (left, right) = self.mix();
+            self.mix_sum.0 += left * 4.0;
+            self.mix_sum.1 += right * 4.0;
+            self.mix_cycles += 4;
+
+            self.sample_phase += 4 * self.sample_rate;
+            if self.sample_phase >= CPU_FREQUENCY {
+                self.sample_phase -= CPU_FREQUENCY;
+                self.push_sample();
+            }
+        }
+    }
+
+    fn power_off(&mut self) {
+        // Everything but wave RAM and, on the DMG, the length counters is cleared
+        let lengths = [
+            self.ch1.length.counter,
+            self.ch2.length.counter,
+            self.ch3.length.counter,
+            self.ch4.length.counter,
+        ];
+        let wave_ram = self.ch3.ram;
+        self.ch1 = PulseChannel::new(true);
+        self.ch2 = PulseChannel::new(false);
+        self.ch3 = WaveChannel::new();
+        self.ch4 = NoiseChannel::new();
+        self.ch1.length.counter = lengths[0];
+        self.ch2.length.counter = lengths[1];
+        self.ch3.length.counter = lengths[2];
+        self.ch4.length.counter = lengths[3];
+        self.ch3.ram = wave_ram;
+        self.nr50 = 0;
+        self.nr51 = 0;
+        self.powered = false;
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        let value = match addr {
+            NR10..=NR14 => self.ch1.read(addr - NR10),
+            NR21..=NR24 => self.ch2.read(addr - NR21 + 1),
+            NR30..=NR34 => self.ch3.read(addr - NR30),
+            NR41..=NR44 => self.ch4.read(addr - NR41 + 1),
+            NR50 => self.nr50,
+            NR51 => self.nr51,
+            NR52 => {
+                ((self.powered as u8) << 7)
+                    | ((self.ch4.enabled as u8) << 3)
+                    | ((self.ch3.enabled as u8) << 2)
+                    | ((self.ch2.enabled as u8) << 1)
+                    | self.ch1.enabled as u8
+            }
+            WAVE_RAM_START..=WAVE_RAM_END => {
+                return self.ch3.ram[(addr - WAVE_RAM_START) as usize];
+            }
+            _ => 0xFF,
+        };
+        match READ_MASKS.get((addr - NR10) as usize) {
+            Some(mask) => value | mask,
+            None => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        if let WAVE_RAM_START..=WAVE_RAM_END = addr {
+            self.ch3.ram[(addr - WAVE_RAM_START) as usize] = value;
+            return;
+        }
+
+        if addr == NR52 {
+            let power = value & NR52_POWER != 0;
+            if self.powered && !power {
+                self.power_off();
+            } else if !self.powered && power {
+                self.powered = true;
+                self.frame_step = 0;
+            }
+            return;
+        }
+
+        // While off only the DMG length counters can be written
+        if !self.powered {
+            match addr {
+                NR11 => self.ch1.length.load(value & 0x3F),
+                NR21 => self.ch2.length.load(value & 0x3F),
+                NR31 => self.ch3.length.load(value),
+                NR41 => self.ch4.length.load(value & 0x3F),
+                _ => (),
+            }
+            return;
+        }
+
+        let skips_length = self.next_step_skips_length();
+        match addr {
+            NR10..=NR14 => self.ch1.write(value, addr - NR10, skips_length),
+            NR21..=NR24 => self.ch2.write(value, addr - NR21 + 1, skips_length),
+            NR30..=NR34 => self.ch3.write(value, addr - NR30, skips_length),
+            NR41..=NR44 => self.ch4.write(value, addr - NR41 + 1, skips_length),
+            NR50 => self.nr50 = value,
+            NR51 => self.nr51 = value,
+            _ => (),
+        }
+    }
+
+    // Takes on the state `loaded` was given by load_state, keeping the output side as it is
+    pub fn restore(&mut self, loaded: Self) {
+        self.powered = loaded.powered;
+        self.nr50 = loaded.nr50;
+        self.nr51 = loaded.nr51;
+        self.ch1 = loaded.ch1;
+        self.ch2 = loaded.ch2;
+        self.ch3 = loaded.ch3;
+        self.ch4 = loaded.ch4;
+        self.frame_step = loaded.frame_step;
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.powered);
+        w.u8(self.nr50);
+        w.u8(self.nr51);
+        self.ch1.save_state(w);
+        self.ch2.save_state(w);
+        self.ch3.save_state(w);
+        self.ch4.save_state(w);
+        w.u8(self.frame_step);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.powered = r.bool()?;
+        self.nr50 = r.u8()?;
+        self.nr51 = r.u8()?;
+        self.ch1.load_state(r)?;
+        self.ch2.load_state(r)?;
+        self.ch3.load_state(r)?;
+        self.ch4.load_state(r)?;
+        self.frame_step = r.u8()?;
+        Ok(())
+    }
+}
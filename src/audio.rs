@@ -0,0 +1,89 @@
+// Plays the APU's output on the default output device through cpal
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+// Anything queued beyond this is dropped so latency can't build up if emulation runs ahead
+const MAX_QUEUED_SECONDS: f32 = 0.1;
+
+type SampleQueue = Arc<Mutex<VecDeque<f32>>>;
+
+pub struct AudioOutput {
+    // Playback stops when the stream is dropped
+    _stream: Stream,
+    queue: SampleQueue,
+    sample_rate: u32,
+}
+
+impl AudioOutput {
+    pub fn open() -> Result<Self, Box<dyn Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let supported = device.default_output_config()?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+
+        let queue = SampleQueue::default();
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, queue.clone())?,
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, queue.clone())?,
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, queue.clone())?,
+            format => return Err(format!("unsupported sample format {format}").into()),
+        };
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Takes interleaved stereo samples
+    pub fn queue(&self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.extend(samples);
+        let max_len = 2 * (self.sample_rate as f32 * MAX_QUEUED_SECONDS) as usize;
+        if queue.len() > max_len {
+            let excess = (queue.len() - max_len) & !1;
+            queue.drain(..excess);
+        }
+    }
+}
+
+fn build_stream<T: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    queue: SampleQueue,
+) -> Result<Stream, cpal::BuildStreamError> {
+    let channels = config.channels as usize;
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let mut queue = queue.lock().unwrap();
+            for frame in data.chunks_mut(channels) {
+                // Underruns play silence
+                let left = queue.pop_front().unwrap_or(0.0);
+                let right = queue.pop_front().unwrap_or(0.0);
+                for (i, sample) in frame.iter_mut().enumerate() {
+                    let value = match (channels, i) {
+                        (1, _) => (left + right) / 2.0,
+                        (_, 0) => left,
+                        (_, 1) => right,
+                        _ => 0.0,
+                    };
+                    *sample = T::from_sample(value);
+                }
+            }
+        },
+        |e| eprintln!("Audio stream error: {e}"),
+        None,
+    )
+}
@@ -0,0 +1,42 @@
+// Reference Manual - https://gbdev.io/pandocs/Power_Up_Sequence.html
+use std::io::{Error, ErrorKind, Result};
+
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+// The CGB boot ROM skips over the cartridge header at 0x0100-0x01FF
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+// Which console to pretend to be. Without a boot ROM this picks the register values the real
+// one would have left behind
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    Dmg,
+    Mgb,
+    Cgb,
+}
+
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    pub fn new(data: Vec<u8>) -> Result<Self> {
+        if data.len() != DMG_BOOT_ROM_SIZE && data.len() != CGB_BOOT_ROM_SIZE {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "boot ROM is {} bytes, expected {DMG_BOOT_ROM_SIZE} (DMG/MGB) or {CGB_BOOT_ROM_SIZE} (CGB)",
+                    data.len()
+                ),
+            ));
+        }
+        Ok(Self { data })
+    }
+
+    // None where the cartridge shows through
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x0000..=0x00FF | 0x0200..=0x08FF => self.data.get(addr as usize).copied(),
+            _ => None,
+        }
+    }
+}
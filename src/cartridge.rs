@@ -0,0 +1,977 @@
+// Reference Manual - https://gbdev.io/pandocs/The_Cartridge_Header.html
+mod camera;
+mod huc1;
+mod huc3;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+
+use camera::PocketCamera;
+pub use camera::{ImageSource, StaticImage};
+use huc1::HuC1;
+use huc3::HuC3;
+use mbc6::Mbc6;
+use mbc7::Mbc7;
+use mmm01::Mmm01;
+
+use crate::rtc::{
+    Rtc, SystemClock, TimeSource, RTC_DAY_HIGH, RTC_FOOTER_SIZE, RTC_FOOTER_SIZE_32, RTC_SECONDS,
+};
+use crate::savestate::{StateReader, StateWriter};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+const HEADER_END: usize = 0x0150;
+const TITLE_START: usize = 0x0134;
+const TITLE_END: usize = 0x0143;
+const CGB_FLAG: usize = 0x0143;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+const BATTERY_CARTRIDGE_TYPES: [u8; 14] = [
+    0x03, 0x06, 0x09, 0x0D, 0x0F, 0x10, 0x13, 0x1B, 0x1E, 0x20, 0x22, 0xFC, 0xFE, 0xFF,
+];
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+const MBC2_RAM_SIZE: usize = 0x200;
+
+fn invalid_rom(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    DmgOnly,
+    CgbCompatible,
+    CgbOnly,
+}
+
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb: CgbSupport,
+    pub cartridge_type: u8,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self> {
+        if rom.len() < HEADER_END {
+            return Err(invalid_rom(format!(
+                "ROM is truncated: {} bytes is too small to hold a cartridge header",
+                rom.len()
+            )));
+        }
+
+        // CGB titles reuse the last title byte for the CGB flag
+        let cgb = match rom[CGB_FLAG] {
+            0x80 => CgbSupport::CgbCompatible,
+            0xC0 => CgbSupport::CgbOnly,
+            _ => CgbSupport::DmgOnly,
+        };
+        let title_end = if cgb == CgbSupport::DmgOnly {
+            TITLE_END + 1
+        } else {
+            TITLE_END
+        };
+        let title = rom[TITLE_START..title_end]
+            .iter()
+            .take_while(|&&b| b != 0)
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '?'
+                }
+            })
+            .collect::<String>();
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => (2 * ROM_BANK_SIZE) << code,
+            0x52 => 72 * ROM_BANK_SIZE,
+            0x53 => 80 * ROM_BANK_SIZE,
+            0x54 => 96 * ROM_BANK_SIZE,
+            code => {
+                return Err(invalid_rom(format!(
+                    "unknown ROM size code 0x{:02X} in cartridge header",
+                    code
+                )));
+            }
+        };
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => {
+                return Err(invalid_rom(format!(
+                    "unknown RAM size code 0x{:02X} in cartridge header",
+                    code
+                )));
+            }
+        };
+
+        Ok(Self {
+            title,
+            cgb,
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            rom_size,
+            ram_size,
+            header_checksum: rom[HEADER_CHECKSUM],
+            global_checksum: ((rom[GLOBAL_CHECKSUM] as u16) << 8) | rom[GLOBAL_CHECKSUM + 1] as u16,
+        })
+    }
+
+    pub fn mbc_type(&self) -> MbcType {
+        match self.cartridge_type {
+            0x00 | 0x08 | 0x09 => MbcType::RomOnly,
+            0x01..=0x03 => MbcType::Mbc1(Mbc1::new()),
+            0x05 | 0x06 => MbcType::Mbc2(Mbc2::new()),
+            0x0F..=0x13 => MbcType::Mbc3(Mbc3::new(self.has_rtc())),
+            0x0B..=0x0D => MbcType::Mmm01(Mmm01::new()),
+            0x19..=0x1E => MbcType::Mbc5(Mbc5::new(self.has_rumble())),
+            0x20 => MbcType::Mbc6(Mbc6::new()),
+            0x22 => MbcType::Mbc7(Mbc7::new()),
+            0xFC => MbcType::PocketCamera(PocketCamera::new()),
+            0xFE => MbcType::HuC3(HuC3::new()),
+            0xFF => MbcType::HuC1(HuC1::new()),
+            _ => MbcType::Unknown,
+        }
+    }
+
+    pub fn has_rtc(&self) -> bool {
+        matches!(self.cartridge_type, 0x0F | 0x10)
+    }
+
+    pub fn has_battery(&self) -> bool {
+        BATTERY_CARTRIDGE_TYPES.contains(&self.cartridge_type)
+    }
+
+    pub fn has_rumble(&self) -> bool {
+        matches!(self.cartridge_type, 0x1C..=0x1E)
+    }
+
+    // Size of the cartridge RAM actually present, MBC2 has its RAM built in and MBC7 an EEPROM
+    pub fn cartridge_ram_size(&self) -> usize {
+        match self.mbc_type() {
+            MbcType::Mbc2(_) => MBC2_RAM_SIZE,
+            MbcType::Mbc7(_) => mbc7::EEPROM_SIZE,
+            _ => self.ram_size,
+        }
+    }
+
+    // https://gbdev.io/pandocs/The_Cartridge_Header.html#014d--header-checksum
+    pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+        rom[TITLE_START..HEADER_CHECKSUM]
+            .iter()
+            .fold(0u8, |acc, &b| acc.wrapping_sub(b).wrapping_sub(1))
+    }
+
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|&(i, _)| i != GLOBAL_CHECKSUM && i != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |acc, (_, &b)| acc.wrapping_add(b as u16))
+    }
+
+    // 32KB ROM with valid checksums that runs `entry` from 0x0100, which has room for 4 bytes
+    #[cfg(test)]
+    pub fn test_rom(cartridge_type: u8, ram_size_code: u8, entry: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0100..0x0100 + entry.len()].copy_from_slice(entry);
+        rom[CARTRIDGE_TYPE] = cartridge_type;
+        rom[RAM_SIZE] = ram_size_code;
+        rom[HEADER_CHECKSUM] = Self::compute_header_checksum(&rom);
+        let checksum = Self::compute_global_checksum(&rom);
+        rom[GLOBAL_CHECKSUM..GLOBAL_CHECKSUM + 2].copy_from_slice(&checksum.to_be_bytes());
+        rom
+    }
+}
+
+// https://gbdev.io/pandocs/MBC1.html
+#[derive(Clone, Debug)]
+pub struct Mbc1 {
+    ram_enabled: bool,
+    bank1: u8,
+    bank2: u8,
+    advanced_mode: bool,
+    // MBC1M multicarts only wire 4 bits of BANK1 to the ROM
+    multicart: bool,
+}
+
+impl Mbc1 {
+    fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            advanced_mode: false,
+            multicart: false,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.u8(self.bank1);
+        w.u8(self.bank2);
+        w.bool(self.advanced_mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        self.bank1 = r.u8()?;
+        self.bank2 = r.u8()?;
+        self.advanced_mode = r.bool()?;
+        Ok(())
+    }
+
+    fn bank2_shift(&self) -> usize {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        if self.advanced_mode {
+            (self.bank2 as usize) << self.bank2_shift()
+        } else {
+            0
+        }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        let bank1 = if self.multicart {
+            self.bank1 & 0x0F
+        } else {
+            self.bank1
+        };
+        ((self.bank2 as usize) << self.bank2_shift()) | bank1 as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_mode {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+
+    fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // The zero check happens on the full 5-bit value, so 0x20 maps to bank 1
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0b11,
+            0x6000..=0x7FFF => self.advanced_mode = value & 1 != 0,
+            _ => (),
+        }
+    }
+}
+
+// https://gbdev.io/pandocs/MBC2.html
+#[derive(Clone, Debug)]
+pub struct Mbc2 {
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u8()?;
+        Ok(())
+    }
+
+    fn write(&mut self, value: u8, addr: u16) {
+        if let 0x0000..=0x3FFF = addr {
+            // Bit 8 of the address selects between the two registers
+            if addr & 0x0100 == 0 {
+                self.ram_enabled = value & 0x0F == 0x0A;
+            } else {
+                self.rom_bank = value & 0x0F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+        }
+    }
+}
+
+// https://gbdev.io/pandocs/MBC3.html
+pub struct Mbc3 {
+    ram_enabled: bool,
+    rom_bank: u8,
+    // 0x00-0x07 selects a RAM bank, 0x08-0x0C an RTC register
+    ram_select: u8,
+    latch_armed: bool,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    fn new(has_rtc: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch_armed: false,
+            rtc: has_rtc.then(|| Rtc::with_time_source(Box::new(SystemClock))),
+        }
+    }
+
+    fn save_state(&mut self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_select);
+        w.bool(self.latch_armed);
+        if let Some(rtc) = &mut self.rtc {
+            rtc.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.ram_select = r.u8()?;
+        self.latch_armed = r.bool()?;
+        match &mut self.rtc {
+            Some(rtc) => rtc.load_state(r),
+            None => Ok(()),
+        }
+    }
+
+    fn selected_rtc_register(&self) -> Option<u8> {
+        match self.ram_select {
+            RTC_SECONDS..=RTC_DAY_HIGH if self.rtc.is_some() => Some(self.ram_select),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, value: u8, addr: u16, large_rom: bool) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // MBC30 carts over 2MB wire up the eighth bank bit
+                self.rom_bank = if large_rom { value } else { value & 0x7F };
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_select = value & 0x0F,
+            0x6000..=0x7FFF => {
+                // Writing 0x00 then 0x01 copies the live clock into the latched registers
+                if self.latch_armed
+                    && value == 0x01
+                    && let Some(rtc) = &mut self.rtc
+                {
+                    rtc.latch();
+                }
+                self.latch_armed = value == 0x00;
+            }
+            _ => (),
+        }
+    }
+}
+
+// https://gbdev.io/pandocs/MBC5.html
+#[derive(Clone, Debug)]
+pub struct Mbc5 {
+    ram_enabled: bool,
+    rom_bank: u16,
+    ram_bank: u8,
+    // Rumble carts repurpose bit 3 of the RAM bank register to drive the motor
+    rumble: Option<bool>,
+}
+
+impl Mbc5 {
+    fn new(has_rumble: bool) -> Self {
+        Self {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble: has_rumble.then_some(false),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.u16(self.rom_bank);
+        w.u8(self.ram_bank);
+        w.bool(self.rumble == Some(true));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u16()?;
+        self.ram_bank = r.u8()?;
+        let rumble = r.bool()?;
+        if let Some(motor) = &mut self.rumble {
+            *motor = rumble;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // Unlike the older mappers bank 0 can be mapped into the upper window
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 1) << 8),
+            0x4000..=0x5FFF => match &mut self.rumble {
+                Some(motor) => {
+                    *motor = value & 0b1000 != 0;
+                    self.ram_bank = value & 0b0111;
+                }
+                None => self.ram_bank = value & 0x0F,
+            },
+            _ => (),
+        }
+    }
+}
+
+pub enum MbcType {
+    RomOnly,
+    Mbc1(Mbc1),
+    Mbc2(Mbc2),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+    Mbc6(Mbc6),
+    Mbc7(Mbc7),
+    Mmm01(Mmm01),
+    HuC1(HuC1),
+    HuC3(HuC3),
+    PocketCamera(PocketCamera),
+    Unknown,
+}
+
+impl MbcType {
+    // Trades the clock and camera, which belong to the frontend rather than the saved state
+    fn swap_sources(&mut self, other: &mut MbcType) {
+        match (self, other) {
+            (
+                MbcType::Mbc3(Mbc3 { rtc: Some(a), .. }),
+                MbcType::Mbc3(Mbc3 { rtc: Some(b), .. }),
+            ) => a.swap_time_source(b),
+            (MbcType::HuC3(a), MbcType::HuC3(b)) => a.swap_time_source(b),
+            (MbcType::PocketCamera(a), MbcType::PocketCamera(b)) => a.swap_image_source(b),
+            _ => (),
+        }
+    }
+}
+
+// Mapper state and RAM read from a save state, waiting to be swapped in
+pub struct CartridgeState {
+    mbc: MbcType,
+    ram: Vec<u8>,
+}
+
+pub struct Cartridge {
+    pub header: CartridgeHeader,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: MbcType,
+    // Where battery-backed RAM is persisted, None for carts without a battery or loaded from memory
+    save_path: Option<PathBuf>,
+    save_dirty: bool,
+}
+
+impl Cartridge {
+    pub fn load_rom(path: &str) -> Result<Self> {
+        Self::load_rom_saving_to(path, Path::new(path).with_extension("sav"))
+    }
+
+    // Like load_rom, but with the battery save kept at save_path instead of next to the ROM
+    pub fn load_rom_saving_to(path: &str, save_path: PathBuf) -> Result<Self> {
+        let rom = fs::read(path)?;
+        let mut cart = Self::from_bytes(rom)?;
+        if cart.header.has_battery() {
+            if save_path.exists() {
+                let data = fs::read(&save_path)?;
+                cart.load_save_data(&data);
+            }
+            cart.save_path = Some(save_path);
+        }
+        Ok(cart)
+    }
+
+    pub fn from_bytes(mut rom: Vec<u8>) -> Result<Self> {
+        // MMM01 multicarts boot into a menu at the end of the ROM, and it holds the real header
+        let menu_offset = mmm01::menu_offset(&rom);
+        let header = CartridgeHeader::parse(&rom[menu_offset.unwrap_or(0)..])?;
+
+        if rom.len() < header.rom_size {
+            return Err(invalid_rom(format!(
+                "ROM size mismatch: header declares {} bytes but the image is {} bytes",
+                header.rom_size,
+                rom.len()
+            )));
+        }
+        // Overdumps repeat the ROM or pad it, either way only the declared size is real
+        rom.truncate(header.rom_size);
+
+        let header_checksum =
+            CartridgeHeader::compute_header_checksum(&rom[menu_offset.unwrap_or(0)..]);
+        if header_checksum != header.header_checksum {
+            return Err(invalid_rom(format!(
+                "header checksum mismatch: expected 0x{:02X}, computed 0x{:02X}",
+                header.header_checksum, header_checksum
+            )));
+        }
+
+        // The hardware never checks this one and plenty of homebrew and hacks get it wrong, so
+        // it's only worth a warning. Each game in an MMM01 cart carries its own
+        let global_checksum = CartridgeHeader::compute_global_checksum(&rom);
+        if menu_offset.is_none() && global_checksum != header.global_checksum {
+            eprintln!(
+                "warning: global checksum mismatch: expected 0x{:04X}, computed 0x{:04X}",
+                header.global_checksum, global_checksum
+            );
+        }
+
+        let mbc = Self::new_mapper(&header, &rom);
+        if matches!(mbc, MbcType::Unknown) {
+            return Err(invalid_rom(format!(
+                "unsupported cartridge type 0x{:02X}",
+                header.cartridge_type
+            )));
+        }
+
+        let ram_size = header.cartridge_ram_size();
+
+        Ok(Self {
+            header,
+            rom,
+            ram: vec![0; ram_size],
+            mbc,
+            save_path: None,
+            save_dirty: false,
+        })
+    }
+
+    // Battery-backed state in the .sav layout other emulators use: RAM, then flash or clock data
+    pub fn save_data(&mut self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        match &mut self.mbc {
+            MbcType::Mbc3(Mbc3 { rtc: Some(rtc), .. }) => data.extend(rtc.save_footer()),
+            MbcType::Mbc6(mbc6) => data.extend_from_slice(&mbc6.flash),
+            MbcType::HuC3(huc3) => data.extend(huc3.save_footer()),
+            _ => (),
+        }
+        data
+    }
+
+    // Short or oversized saves are loaded as far as they go rather than rejected
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let ram_len = self.ram.len().min(data.len());
+        self.ram[..ram_len].copy_from_slice(&data[..ram_len]);
+        let extra = &data[ram_len..];
+
+        match &mut self.mbc {
+            MbcType::Mbc3(Mbc3 { rtc: Some(rtc), .. })
+                if extra.len() == RTC_FOOTER_SIZE || extra.len() == RTC_FOOTER_SIZE_32 =>
+            {
+                rtc.load_footer(extra)
+            }
+            MbcType::Mbc6(mbc6) => {
+                let flash_len = mbc6.flash.len().min(extra.len());
+                mbc6.flash[..flash_len].copy_from_slice(&extra[..flash_len]);
+            }
+            MbcType::HuC3(huc3) if extra.len() >= huc3::HUC3_FOOTER_SIZE => huc3.load_footer(extra),
+            _ => (),
+        }
+    }
+
+    // Writes the .sav if anything changed since the last flush
+    pub fn flush_save(&mut self) -> Result<()> {
+        if !self.save_dirty {
+            return Ok(());
+        }
+        let Some(save_path) = self.save_path.clone() else {
+            return Ok(());
+        };
+
+        // Write alongside and rename so a crash mid-write can't truncate the old save
+        let data = self.save_data();
+        let tmp_path = save_path.with_extension("sav.tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &save_path)?;
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    // Identifies the ROM a save state belongs to
+    pub fn rom_checksum(&self) -> u16 {
+        CartridgeHeader::compute_global_checksum(&self.rom)
+    }
+
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        match &mut self.mbc {
+            MbcType::Mbc1(mbc1) => mbc1.save_state(w),
+            MbcType::Mbc2(mbc2) => mbc2.save_state(w),
+            MbcType::Mbc3(mbc3) => mbc3.save_state(w),
+            MbcType::Mbc5(mbc5) => mbc5.save_state(w),
+            MbcType::Mbc6(mbc6) => mbc6.save_state(w),
+            MbcType::Mbc7(mbc7) => mbc7.save_state(w),
+            MbcType::Mmm01(mmm01) => mmm01.save_state(w),
+            MbcType::HuC1(huc1) => huc1.save_state(w),
+            MbcType::HuC3(huc3) => huc3.save_state(w),
+            MbcType::PocketCamera(camera) => camera.save_state(w),
+            MbcType::RomOnly | MbcType::Unknown => (),
+        }
+        w.bytes(&self.ram);
+    }
+
+    fn new_mapper(header: &CartridgeHeader, rom: &[u8]) -> MbcType {
+        let mut mbc = header.mbc_type();
+        if let MbcType::Mbc1(mbc1) = &mut mbc {
+            mbc1.multicart = is_mbc1_multicart(rom);
+        }
+        mbc
+    }
+
+    // Reads into a fresh mapper and leaves this one alone until restore is called with the result
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<CartridgeState> {
+        let mut mbc = Self::new_mapper(&self.header, &self.rom);
+        // The clocks catch up with their time source as they load, so they need the real one
+        mbc.swap_sources(&mut self.mbc);
+        let loaded = Self::load_mapper_state(&mut mbc, r).and_then(|()| r.bytes(self.ram.len()));
+        mbc.swap_sources(&mut self.mbc);
+        Ok(CartridgeState {
+            mbc,
+            ram: loaded?.to_vec(),
+        })
+    }
+
+    pub fn restore(&mut self, mut state: CartridgeState) {
+        state.mbc.swap_sources(&mut self.mbc);
+        self.mbc = state.mbc;
+        self.ram = state.ram;
+        // The restored RAM replaces whatever is in the .sav
+        self.save_dirty = true;
+    }
+
+    fn load_mapper_state(mbc: &mut MbcType, r: &mut StateReader) -> Result<()> {
+        match mbc {
+            MbcType::Mbc1(mbc1) => mbc1.load_state(r)?,
+            MbcType::Mbc2(mbc2) => mbc2.load_state(r)?,
+            MbcType::Mbc3(mbc3) => mbc3.load_state(r)?,
+            MbcType::Mbc5(mbc5) => mbc5.load_state(r)?,
+            MbcType::Mbc6(mbc6) => mbc6.load_state(r)?,
+            MbcType::Mbc7(mbc7) => mbc7.load_state(r)?,
+            MbcType::Mmm01(mmm01) => mmm01.load_state(r)?,
+            MbcType::HuC1(huc1) => huc1.load_state(r)?,
+            MbcType::HuC3(huc3) => huc3.load_state(r)?,
+            MbcType::PocketCamera(camera) => camera.load_state(r)?,
+            MbcType::RomOnly | MbcType::Unknown => (),
+        }
+        Ok(())
+    }
+
+    pub fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        match &mut self.mbc {
+            MbcType::Mbc3(Mbc3 { rtc: Some(rtc), .. }) => rtc.set_time_source(time_source),
+            MbcType::HuC3(huc3) => huc3.set_time_source(time_source),
+            _ => (),
+        }
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        if let MbcType::Mbc7(mbc7) = &mut self.mbc {
+            mbc7.set_tilt(x, y);
+        }
+    }
+
+    pub fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        if let MbcType::PocketCamera(camera) = &mut self.mbc {
+            camera.set_image_source(source);
+        }
+    }
+
+    pub fn rumble(&self) -> bool {
+        matches!(
+            self.mbc,
+            MbcType::Mbc5(Mbc5 {
+                rumble: Some(true),
+                ..
+            })
+        )
+    }
+
+    fn rom_byte(&self, bank: usize, addr: u16) -> u8 {
+        let offset = bank * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1));
+        self.rom[offset % self.rom.len()]
+    }
+
+    fn ram_offset(&self, bank: usize, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() {
+            return None;
+        }
+        let offset = bank * RAM_BANK_SIZE + (addr as usize & (RAM_BANK_SIZE - 1));
+        Some(offset % self.ram.len())
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        match &self.mbc {
+            MbcType::Mbc1(mbc1) => mbc1.low_rom_bank(),
+            MbcType::Mmm01(mmm01) => mmm01.low_rom_bank(self.rom.len() / ROM_BANK_SIZE),
+            _ => 0,
+        }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        match &self.mbc {
+            MbcType::Mbc1(mbc1) => mbc1.high_rom_bank(),
+            MbcType::Mbc2(mbc2) => mbc2.rom_bank as usize,
+            MbcType::Mbc3(mbc3) => mbc3.rom_bank as usize,
+            MbcType::Mbc5(mbc5) => mbc5.rom_bank as usize,
+            MbcType::Mbc7(mbc7) => mbc7.rom_bank as usize,
+            MbcType::Mmm01(mmm01) => mmm01.high_rom_bank(self.rom.len() / ROM_BANK_SIZE),
+            MbcType::HuC1(huc1) => huc1.rom_bank as usize,
+            MbcType::HuC3(huc3) => huc3.rom_bank as usize,
+            MbcType::PocketCamera(camera) => camera.rom_bank as usize,
+            // MBC6 banks its upper window in 8KB halves, see Mbc6::read_rom
+            MbcType::RomOnly | MbcType::Mbc6(_) | MbcType::Unknown => 1,
+        }
+    }
+
+    // Offset into `ram` for an access to 0xA000-0xBFFF, None if RAM is disabled or absent
+    fn mapped_ram(&self, addr: u16) -> Option<usize> {
+        match &self.mbc {
+            MbcType::RomOnly => self.ram_offset(0, addr),
+            MbcType::Mbc1(mbc1) if mbc1.ram_enabled => self.ram_offset(mbc1.ram_bank(), addr),
+            // The 512 half-bytes of MBC2 RAM repeat across the whole region
+            MbcType::Mbc2(mbc2) if mbc2.ram_enabled => Some(addr as usize & (MBC2_RAM_SIZE - 1)),
+            MbcType::Mbc3(mbc3) if mbc3.ram_enabled && mbc3.ram_select < 0x08 => {
+                self.ram_offset(mbc3.ram_select as usize, addr)
+            }
+            MbcType::Mbc5(mbc5) if mbc5.ram_enabled => {
+                self.ram_offset(mbc5.ram_bank as usize, addr)
+            }
+            MbcType::Mbc6(mbc6) if mbc6.ram_enabled && !self.ram.is_empty() => {
+                Some(mbc6.ram_offset(addr) % self.ram.len())
+            }
+            MbcType::Mmm01(mmm01) if mmm01.ram_enabled => self.ram_offset(mmm01.ram_bank(), addr),
+            MbcType::HuC1(huc1) if !huc1.ir_mode => self.ram_offset(huc1.ram_bank as usize, addr),
+            MbcType::HuC3(huc3) if huc3.ram_mapped() => {
+                self.ram_offset(huc3.ram_bank as usize, addr)
+            }
+            MbcType::PocketCamera(camera) if !camera.registers_mapped => {
+                self.ram_offset(camera.ram_bank as usize, addr)
+            }
+            _ => None,
+        }
+    }
+
+    fn ram_byte(&self, addr: u16) -> u8 {
+        match self.mapped_ram(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF,
+        }
+    }
+
+    fn read_ram(&self, addr: u16) -> u8 {
+        match &self.mbc {
+            // Only the low nibble exists, the upper bits float high
+            MbcType::Mbc2(_) => self.ram_byte(addr) | 0xF0,
+            MbcType::Mbc3(mbc3) if mbc3.ram_enabled => {
+                match (mbc3.selected_rtc_register(), &mbc3.rtc) {
+                    (Some(reg), Some(rtc)) => rtc.read(reg),
+                    _ => self.ram_byte(addr),
+                }
+            }
+            MbcType::Mbc7(mbc7) => mbc7.read(addr),
+            MbcType::HuC1(huc1) if huc1.ir_mode => huc1.read_ir(),
+            MbcType::HuC3(huc3) => match huc3.read_register() {
+                Some(value) => value,
+                None => self.ram_byte(addr),
+            },
+            MbcType::PocketCamera(camera) if camera.registers_mapped => camera.read_register(addr),
+            _ => self.ram_byte(addr),
+        }
+    }
+
+    // Anything stored here that ends up in the .sav marks it as needing a flush
+    fn write_ram(&mut self, value: u8, addr: u16) {
+        match &mut self.mbc {
+            MbcType::Mbc3(mbc3) => {
+                if let (true, Some(reg)) = (mbc3.ram_enabled, mbc3.selected_rtc_register()) {
+                    if let Some(rtc) = &mut mbc3.rtc {
+                        self.save_dirty |= rtc.write(reg, value);
+                    }
+                    return;
+                }
+            }
+            MbcType::Mbc7(mbc7) => {
+                self.save_dirty |= mbc7.write_register(value, addr, &mut self.ram);
+                return;
+            }
+            MbcType::HuC1(huc1) if huc1.ir_mode => return,
+            MbcType::HuC3(huc3) if !huc3.ram_writable() => {
+                self.save_dirty |= huc3.write_register(value);
+                return;
+            }
+            MbcType::PocketCamera(camera) => {
+                if camera.registers_mapped {
+                    self.save_dirty |= camera.write_register(value, addr, &mut self.ram);
+                    return;
+                }
+                if !camera.ram_write_enabled {
+                    return;
+                }
+            }
+            _ => (),
+        }
+
+        if let Some(offset) = self.mapped_ram(addr) {
+            let value = match self.mbc {
+                MbcType::Mbc2(_) => value & 0x0F,
+                _ => value,
+            };
+            if self.ram[offset] != value {
+                self.ram[offset] = value;
+                self.save_dirty = true;
+            }
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => self.rom_byte(self.low_rom_bank(), addr),
+
+            0x4000..=0x7FFF => match &self.mbc {
+                MbcType::Mbc6(mbc6) => mbc6.read_rom(&self.rom, addr),
+                _ => self.rom_byte(self.high_rom_bank(), addr),
+            },
+
+            0xA000..=0xBFFF => self.read_ram(addr),
+
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x7FFF => match &mut self.mbc {
+                MbcType::Mbc1(mbc1) => mbc1.write(value, addr),
+                MbcType::Mbc2(mbc2) => mbc2.write(value, addr),
+                MbcType::Mbc3(mbc3) => mbc3.write(value, addr, self.rom.len() > 0x200000),
+                MbcType::Mbc5(mbc5) => mbc5.write(value, addr),
+                // MBC6 flash is programmed through the ROM region
+                MbcType::Mbc6(mbc6) => self.save_dirty |= mbc6.write(value, addr),
+                MbcType::Mbc7(mbc7) => mbc7.write(value, addr),
+                MbcType::Mmm01(mmm01) => mmm01.write(value, addr),
+                MbcType::HuC1(huc1) => huc1.write(value, addr),
+                MbcType::HuC3(huc3) => huc3.write(value, addr),
+                MbcType::PocketCamera(camera) => camera.write(value, addr),
+                MbcType::RomOnly | MbcType::Unknown => (),
+            },
+
+            0xA000..=0xBFFF => self.write_ram(value, addr),
+
+            _ => (),
+        }
+    }
+}
+
+// MBC1M carts are 1MB and repeat the boot logo at the start of each 256KB game
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const LOGO: std::ops::Range<usize> = 0x0104..0x0134;
+    const SECOND_GAME: usize = 0x10 * ROM_BANK_SIZE;
+    rom.len() == 0x100000 && rom[LOGO] == rom[SECOND_GAME + LOGO.start..SECOND_GAME + LOGO.end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_error(rom: Vec<u8>) -> String {
+        match Cartridge::from_bytes(rom) {
+            Ok(_) => panic!("ROM loaded"),
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::InvalidData);
+                e.to_string()
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_header_is_rejected() {
+        let rom = CartridgeHeader::test_rom(0, 0, &[])[..HEADER_END - 1].to_vec();
+        assert!(load_error(rom).contains("truncated"));
+    }
+
+    #[test]
+    fn images_shorter_than_the_declared_size_are_rejected() {
+        let mut rom = CartridgeHeader::test_rom(0, 0, &[]);
+        rom.truncate(ROM_BANK_SIZE);
+        assert!(load_error(rom).contains("size mismatch"));
+    }
+
+    #[test]
+    fn overdumps_are_cut_down_to_the_declared_size() {
+        let mut rom = CartridgeHeader::test_rom(0, 0, &[]);
+        rom.resize(4 * ROM_BANK_SIZE, 0xFF);
+        let cart = Cartridge::from_bytes(rom).unwrap();
+        assert_eq!(cart.rom.len(), 2 * ROM_BANK_SIZE);
+    }
+
+    #[test]
+    fn bad_header_checksum_is_rejected() {
+        let mut rom = CartridgeHeader::test_rom(0, 0, &[]);
+        rom[HEADER_CHECKSUM] ^= 0xFF;
+        assert!(load_error(rom).contains("header checksum"));
+    }
+
+    #[test]
+    fn a_failed_state_load_leaves_the_save_clean() {
+        let mut cart = Cartridge::from_bytes(CartridgeHeader::test_rom(0x03, 0x02, &[])).unwrap();
+        let mut w = StateWriter::new();
+        cart.save_state(&mut w);
+        let state = w.into_bytes();
+
+        let cut = &state[..state.len() - 1];
+        assert!(cart.load_state(&mut StateReader::new(cut)).is_err());
+        assert!(!cart.save_dirty);
+
+        let loaded = cart.load_state(&mut StateReader::new(&state)).unwrap();
+        assert!(!cart.save_dirty);
+        cart.restore(loaded);
+        assert!(cart.save_dirty);
+    }
+
+    #[test]
+    fn bad_global_checksum_still_loads() {
+        let mut rom = CartridgeHeader::test_rom(0, 0, &[]);
+        rom[GLOBAL_CHECKSUM] ^= 0xFF;
+        assert!(Cartridge::from_bytes(rom).is_ok());
+    }
+}
@@ -0,0 +1,173 @@
+// Reference Manual - https://gbdev.io/pandocs/Gameboy_Camera.html
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+const REGISTER_COUNT: usize = 0x36;
+const CAPTURE_BUSY: u8 = 0b0000_0001;
+const DITHER_MATRIX: usize = 0x06;
+// Captured tiles land in RAM bank 0 just after the first 256 bytes
+const IMAGE_OFFSET: usize = 0x0100;
+
+// Produces CAMERA_WIDTH * CAMERA_HEIGHT greyscale pixels, row-major, 0 is black and 255 white
+pub trait ImageSource {
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+pub struct StaticImage {
+    pixels: Vec<u8>,
+}
+
+impl StaticImage {
+    pub fn new(mut pixels: Vec<u8>) -> Self {
+        pixels.resize(CAMERA_WIDTH * CAMERA_HEIGHT, 0xFF);
+        Self { pixels }
+    }
+
+    // Diagonal gradient, handy as a stand-in when no real image is supplied
+    pub fn test_pattern() -> Self {
+        let pixels = (0..CAMERA_WIDTH * CAMERA_HEIGHT)
+            .map(|i| {
+                let (x, y) = (i % CAMERA_WIDTH, i / CAMERA_WIDTH);
+                ((x + y) * 255 / (CAMERA_WIDTH + CAMERA_HEIGHT - 2)) as u8
+            })
+            .collect();
+        Self { pixels }
+    }
+}
+
+impl ImageSource for StaticImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+pub struct PocketCamera {
+    // RAM is always readable, this only gates writes
+    pub(super) ram_write_enabled: bool,
+    pub(super) rom_bank: u8,
+    pub(super) ram_bank: u8,
+    // Bank select bit 4 swaps RAM for the sensor registers
+    pub(super) registers_mapped: bool,
+    registers: [u8; REGISTER_COUNT],
+    source: Box<dyn ImageSource>,
+}
+
+impl PocketCamera {
+    pub fn new() -> Self {
+        Self {
+            ram_write_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_mapped: false,
+            registers: [0; REGISTER_COUNT],
+            source: Box::new(StaticImage::test_pattern()),
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_write_enabled);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank);
+        w.bool(self.registers_mapped);
+        w.bytes(&self.registers);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_write_enabled = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.ram_bank = r.u8()?;
+        self.registers_mapped = r.bool()?;
+        r.fill(&mut self.registers)
+    }
+
+    pub fn swap_image_source(&mut self, other: &mut PocketCamera) {
+        std::mem::swap(&mut self.source, &mut other.source);
+    }
+
+    pub fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.source = source;
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        // Only the control register can be read back
+        if addr & 0x7F == 0 {
+            self.registers[0] & 0b0000_0111
+        } else {
+            0x00
+        }
+    }
+
+    // Returns true if a capture was written to RAM
+    pub fn write_register(&mut self, value: u8, addr: u16, ram: &mut [u8]) -> bool {
+        let reg = (addr & 0x7F) as usize;
+        if reg >= REGISTER_COUNT {
+            return false;
+        }
+        self.registers[reg] = value;
+        if reg == 0 && value & CAPTURE_BUSY != 0 {
+            return self.capture(ram);
+        }
+        false
+    }
+
+    // Captures finish instantly, so the busy bit is already clear when the game polls it
+    fn capture(&mut self, ram: &mut [u8]) -> bool {
+        let pixels = self.source.capture();
+        if ram.len() < IMAGE_OFFSET + CAMERA_WIDTH * CAMERA_HEIGHT / 4 {
+            return false;
+        }
+
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                let p = pixels.get(y * CAMERA_WIDTH + x).copied().unwrap_or(0xFF);
+
+                // Each cell of the 4x4 dither matrix holds three thresholds
+                let cell = DITHER_MATRIX + ((y % 4) * 4 + (x % 4)) * 3;
+                let thresholds = &self.registers[cell..cell + 3];
+                let color: u8 = if p < thresholds[0] {
+                    3
+                } else if p < thresholds[1] {
+                    2
+                } else if p < thresholds[2] {
+                    1
+                } else {
+                    0
+                };
+
+                let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+                let row = IMAGE_OFFSET + tile * 16 + (y % 8) * 2;
+                let mask = 0x80 >> (x % 8);
+                for (plane, bit) in [(0, color & 1), (1, color >> 1)] {
+                    if bit != 0 {
+                        ram[row + plane] |= mask;
+                    } else {
+                        ram[row + plane] &= !mask;
+                    }
+                }
+            }
+        }
+
+        self.registers[0] &= !CAPTURE_BUSY;
+        true
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_write_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x3F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => {
+                self.registers_mapped = value & 0x10 != 0;
+                self.ram_bank = value & 0x0F;
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// Reference Manual - https://gbdev.io/pandocs/HuC1.html
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+
+// Value read back from the IR receiver when it sees no light
+pub const IR_NO_LIGHT: u8 = 0xC0;
+
+pub struct HuC1 {
+    // 0xA000-0xBFFF shows the IR port instead of RAM
+    pub(super) ir_mode: bool,
+    pub(super) rom_bank: u8,
+    pub(super) ram_bank: u8,
+}
+
+impl HuC1 {
+    pub fn new() -> Self {
+        Self {
+            ir_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ir_mode);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ir_mode = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.ram_bank = r.u8()?;
+        Ok(())
+    }
+
+    pub fn read_ir(&self) -> u8 {
+        // There is never a second cart pointing its LED at us
+        IR_NO_LIGHT
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.ir_mode = value & 0x0F == 0x0E,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0b11,
+            _ => (),
+        }
+    }
+}
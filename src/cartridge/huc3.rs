@@ -0,0 +1,188 @@
+// Reference Manual - https://gbdev.io/pandocs/HuC3.html
+use super::huc1::IR_NO_LIGHT;
+use crate::rtc::{SystemClock, TimeSource};
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+// SameBoy's layout: u64 timestamp, u16 minutes and days, then an alarm we don't emulate
+pub const HUC3_FOOTER_SIZE: usize = 17;
+
+const MODE_RAM_READ_ONLY: u8 = 0x0;
+const MODE_RAM: u8 = 0xA;
+const MODE_COMMAND: u8 = 0xB;
+const MODE_RESPONSE: u8 = 0xC;
+const MODE_SEMAPHORE: u8 = 0xD;
+const MODE_IR: u8 = 0xE;
+
+pub struct HuC3 {
+    // Selects what 0xA000-0xBFFF is connected to
+    pub(super) mode: u8,
+    pub(super) rom_bank: u8,
+    pub(super) ram_bank: u8,
+    access_index: u8,
+    last_command: u8,
+    response: u8,
+    // The clock only counts whole minutes and days
+    minutes: u16,
+    days: u16,
+    seconds: u64,
+    last_update: u64,
+    time_source: Box<dyn TimeSource>,
+}
+
+impl HuC3 {
+    pub fn new() -> Self {
+        let time_source: Box<dyn TimeSource> = Box::new(SystemClock);
+        Self {
+            mode: MODE_RAM_READ_ONLY,
+            rom_bank: 1,
+            ram_bank: 0,
+            access_index: 0,
+            last_command: 0,
+            response: 0,
+            minutes: 0,
+            days: 0,
+            seconds: 0,
+            last_update: time_source.now(),
+            time_source,
+        }
+    }
+
+    pub fn swap_time_source(&mut self, other: &mut HuC3) {
+        std::mem::swap(&mut self.time_source, &mut other.time_source);
+    }
+
+    pub fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.update();
+        self.last_update = time_source.now();
+        self.time_source = time_source;
+    }
+
+    fn update(&mut self) {
+        let now = self.time_source.now();
+        self.seconds += now.saturating_sub(self.last_update);
+        self.last_update = now;
+
+        let minutes = self.minutes as u64 + self.seconds / 60;
+        self.seconds %= 60;
+        self.minutes = (minutes % MINUTES_PER_DAY) as u16;
+        self.days = self.days.wrapping_add((minutes / MINUTES_PER_DAY) as u16);
+    }
+
+    pub fn save_footer(&mut self) -> Vec<u8> {
+        self.update();
+        let mut footer = Vec::with_capacity(HUC3_FOOTER_SIZE);
+        footer.extend_from_slice(&self.last_update.to_le_bytes());
+        footer.extend_from_slice(&self.minutes.to_le_bytes());
+        footer.extend_from_slice(&self.days.to_le_bytes());
+        footer.resize(HUC3_FOOTER_SIZE, 0);
+        footer
+    }
+
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        self.last_update = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+        self.minutes = u16::from_le_bytes([footer[8], footer[9]]);
+        self.days = u16::from_le_bytes([footer[10], footer[11]]);
+        self.seconds = 0;
+        self.update();
+    }
+
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        w.u8(self.mode);
+        w.u8(self.rom_bank);
+        w.u8(self.ram_bank);
+        w.u8(self.access_index);
+        w.u8(self.last_command);
+        w.u8(self.response);
+        w.bytes(&self.save_footer());
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.mode = r.u8()?;
+        self.rom_bank = r.u8()?;
+        self.ram_bank = r.u8()?;
+        self.access_index = r.u8()?;
+        self.last_command = r.u8()?;
+        self.response = r.u8()?;
+        self.load_footer(r.bytes(HUC3_FOOTER_SIZE)?);
+        Ok(())
+    }
+
+    pub fn ram_mapped(&self) -> bool {
+        self.mode == MODE_RAM || self.mode == MODE_RAM_READ_ONLY
+    }
+
+    pub fn ram_writable(&self) -> bool {
+        self.mode == MODE_RAM
+    }
+
+    // None when the region is showing RAM
+    pub fn read_register(&self) -> Option<u8> {
+        match self.mode {
+            MODE_RAM | MODE_RAM_READ_ONLY => None,
+            MODE_RESPONSE => Some(0x80 | (self.last_command & 0x70) | self.response),
+            // Commands complete instantly so the clock is always ready
+            MODE_SEMAPHORE => Some(0x01),
+            MODE_IR => Some(IR_NO_LIGHT),
+            _ => Some(0xFF),
+        }
+    }
+
+    // Returns true if the command set the clock
+    pub fn write_register(&mut self, value: u8) -> bool {
+        self.mode == MODE_COMMAND && self.run_command(value)
+    }
+
+    // The clock is reached through nibble-wide reads and writes to an indexed register file
+    fn run_command(&mut self, value: u8) -> bool {
+        self.update();
+        self.last_command = value;
+        let arg = value & 0x0F;
+        let index = self.access_index;
+
+        match (value >> 4) & 0x07 {
+            0x0 | 0x1 => {
+                self.response = match index {
+                    0..=2 => (self.minutes >> (index * 4)) as u8 & 0x0F,
+                    3..=6 => (self.days >> ((index - 3) * 4)) as u8 & 0x0F,
+                    _ => 0,
+                };
+                if value >> 4 == 0x1 {
+                    self.access_index = index.wrapping_add(1);
+                }
+            }
+            0x2 | 0x3 => {
+                match index {
+                    0..=2 => {
+                        let shift = index * 4;
+                        self.minutes = (self.minutes & !(0x0F << shift)) | ((arg as u16) << shift);
+                    }
+                    3..=6 => {
+                        let shift = (index - 3) * 4;
+                        self.days = (self.days & !(0x0F << shift)) | ((arg as u16) << shift);
+                    }
+                    _ => (),
+                }
+                if value >> 4 == 0x3 {
+                    self.access_index = index.wrapping_add(1);
+                }
+                return index <= 6;
+            }
+            0x4 => self.access_index = (index & 0xF0) | arg,
+            0x5 => self.access_index = (index & 0x0F) | (arg << 4),
+            _ => (),
+        }
+        false
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => (),
+        }
+    }
+}
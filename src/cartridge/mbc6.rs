@@ -0,0 +1,200 @@
+// Reference Manual - https://gbdev.io/pandocs/MBC6.html
+use crate::savestate::{invalid_state, StateReader, StateWriter};
+use std::io::Result;
+
+const HALF_BANK_SIZE: usize = 0x2000;
+const RAM_HALF_BANK_SIZE: usize = 0x1000;
+pub const FLASH_SIZE: usize = 0x100000;
+const FLASH_SECTOR_SIZE: usize = 0x2000;
+
+// Macronix MX29F008 JEDEC ID
+const FLASH_MANUFACTURER: u8 = 0xC2;
+const FLASH_DEVICE: u8 = 0x81;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum FlashState {
+    Read,
+    Unlock1,
+    Unlock2,
+    Id,
+    Program,
+    EraseSetup,
+    EraseUnlock1,
+    EraseUnlock2,
+}
+
+impl FlashState {
+    fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Read),
+            1 => Ok(Self::Unlock1),
+            2 => Ok(Self::Unlock2),
+            3 => Ok(Self::Id),
+            4 => Ok(Self::Program),
+            5 => Ok(Self::EraseSetup),
+            6 => Ok(Self::EraseUnlock1),
+            7 => Ok(Self::EraseUnlock2),
+            _ => Err(invalid_state(format!("invalid flash state {value}"))),
+        }
+    }
+}
+
+pub struct Mbc6 {
+    pub(super) ram_enabled: bool,
+    // Each half of 0x4000-0x7FFF and 0xA000-0xBFFF is banked independently
+    ram_banks: [u8; 2],
+    rom_banks: [u8; 2],
+    flash_selected: [bool; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    flash_state: FlashState,
+    pub(super) flash: Vec<u8>,
+}
+
+impl Mbc6 {
+    pub fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            ram_banks: [0, 1],
+            // Mirrors what a plain cart shows at 0x4000 until the game picks banks
+            rom_banks: [2, 3],
+            flash_selected: [false, false],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            flash_state: FlashState::Read,
+            flash: vec![0xFF; FLASH_SIZE],
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.bytes(&self.ram_banks);
+        w.bytes(&self.rom_banks);
+        w.bool(self.flash_selected[0]);
+        w.bool(self.flash_selected[1]);
+        w.bool(self.flash_enabled);
+        w.bool(self.flash_write_enabled);
+        w.u8(self.flash_state as u8);
+        w.bytes(&self.flash);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        r.fill(&mut self.ram_banks)?;
+        r.fill(&mut self.rom_banks)?;
+        self.flash_selected = [r.bool()?, r.bool()?];
+        self.flash_enabled = r.bool()?;
+        self.flash_write_enabled = r.bool()?;
+        self.flash_state = FlashState::from_u8(r.u8()?)?;
+        r.fill(&mut self.flash)
+    }
+
+    fn half(addr: u16, size: usize) -> usize {
+        (addr as usize / size) & 1
+    }
+
+    fn flash_offset(&self, half: usize, addr: u16) -> usize {
+        (self.rom_banks[half] as usize * HALF_BANK_SIZE + (addr as usize & (HALF_BANK_SIZE - 1)))
+            % FLASH_SIZE
+    }
+
+    pub fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let half = Self::half(addr, HALF_BANK_SIZE);
+        if !self.flash_selected[half] {
+            let offset = self.rom_banks[half] as usize * HALF_BANK_SIZE
+                + (addr as usize & (HALF_BANK_SIZE - 1));
+            return rom[offset % rom.len()];
+        }
+
+        if !self.flash_enabled {
+            return 0xFF;
+        }
+        let offset = self.flash_offset(half, addr);
+        match self.flash_state {
+            FlashState::Id => {
+                if offset & 1 == 0 {
+                    FLASH_MANUFACTURER
+                } else {
+                    FLASH_DEVICE
+                }
+            }
+            _ => self.flash[offset],
+        }
+    }
+
+    pub fn ram_offset(&self, addr: u16) -> usize {
+        let half = Self::half(addr, RAM_HALF_BANK_SIZE);
+        self.ram_banks[half] as usize * RAM_HALF_BANK_SIZE
+            + (addr as usize & (RAM_HALF_BANK_SIZE - 1))
+    }
+
+    // Returns true if a flash byte changed
+    pub fn write(&mut self, value: u8, addr: u16) -> bool {
+        match addr {
+            0x0000..=0x03FF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0400..=0x07FF => self.ram_banks[0] = value & 0x07,
+            0x0800..=0x0BFF => self.ram_banks[1] = value & 0x07,
+            0x0C00..=0x0FFF => self.flash_enabled = value & 1 != 0,
+            0x1000..=0x1FFF => self.flash_write_enabled = value & 1 != 0,
+            0x2000..=0x27FF => self.rom_banks[0] = value & 0x7F,
+            0x2800..=0x2FFF => self.flash_selected[0] = value == 0x08,
+            0x3000..=0x37FF => self.rom_banks[1] = value & 0x7F,
+            0x3800..=0x3FFF => self.flash_selected[1] = value == 0x08,
+            0x4000..=0x7FFF => return self.write_flash(value, addr),
+            _ => (),
+        }
+        false
+    }
+
+    // JEDEC command sequences, every operation completes instantly
+    fn write_flash(&mut self, value: u8, addr: u16) -> bool {
+        let half = Self::half(addr, HALF_BANK_SIZE);
+        if !self.flash_selected[half] || !self.flash_enabled {
+            return false;
+        }
+
+        let offset = self.flash_offset(half, addr);
+        if value == 0xF0 {
+            self.flash_state = FlashState::Read;
+            return false;
+        }
+
+        let mut changed = false;
+
+        self.flash_state = match (self.flash_state, offset & 0x7FFF, value) {
+            (FlashState::Read | FlashState::Id, 0x5555, 0xAA) => FlashState::Unlock1,
+            (FlashState::Unlock1, 0x2AAA, 0x55) => FlashState::Unlock2,
+            (FlashState::Unlock2, 0x5555, 0x90) => FlashState::Id,
+            (FlashState::Unlock2, 0x5555, 0xA0) => FlashState::Program,
+            (FlashState::Unlock2, 0x5555, 0x80) => FlashState::EraseSetup,
+            (FlashState::EraseSetup, 0x5555, 0xAA) => FlashState::EraseUnlock1,
+            (FlashState::EraseUnlock1, 0x2AAA, 0x55) => FlashState::EraseUnlock2,
+            (FlashState::EraseUnlock2, 0x5555, 0x10) => {
+                if self.flash_write_enabled {
+                    changed = self.flash.iter().any(|&b| b != 0xFF);
+                    self.flash.fill(0xFF);
+                }
+                FlashState::Read
+            }
+            (FlashState::EraseUnlock2, _, 0x30) => {
+                if self.flash_write_enabled {
+                    let sector =
+                        &mut self.flash[offset & !(FLASH_SECTOR_SIZE - 1)..][..FLASH_SECTOR_SIZE];
+                    changed = sector.iter().any(|&b| b != 0xFF);
+                    sector.fill(0xFF);
+                }
+                FlashState::Read
+            }
+            (FlashState::Program, _, _) => {
+                // Programming can only clear bits, erasing sets them again
+                if self.flash_write_enabled {
+                    changed = self.flash[offset] & value != self.flash[offset];
+                    self.flash[offset] &= value;
+                }
+                FlashState::Read
+            }
+            _ => FlashState::Read,
+        };
+        changed
+    }
+}
@@ -0,0 +1,343 @@
+// Reference Manual - https://gbdev.io/pandocs/MBC7.html
+use crate::savestate::{invalid_state, StateReader, StateWriter};
+use std::io::Result;
+
+// Accelerometer reading when level, and how far one g of tilt moves it
+const ACCEL_CENTER: f32 = 0x81D0 as f32;
+const ACCEL_ONE_G: f32 = 0x70 as f32;
+const ACCEL_ERASED: u16 = 0x8000;
+
+pub const EEPROM_SIZE: usize = 0x100;
+
+const EEPROM_CS: u8 = 0b1000_0000;
+const EEPROM_CLK: u8 = 0b0100_0000;
+const EEPROM_DI: u8 = 0b0000_0010;
+const EEPROM_DO: u8 = 0b0000_0001;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum EepromState {
+    Idle,
+    Command,
+    Read,
+    Write,
+    WriteAll,
+}
+
+impl EepromState {
+    fn from_u8(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Idle),
+            1 => Ok(Self::Command),
+            2 => Ok(Self::Read),
+            3 => Ok(Self::Write),
+            4 => Ok(Self::WriteAll),
+            _ => Err(invalid_state(format!("invalid EEPROM state {value}"))),
+        }
+    }
+}
+
+// 93LC56 serial EEPROM, bit-banged through a single register and organised as 128 16-bit words
+struct Eeprom {
+    cs: bool,
+    clk: bool,
+    di: bool,
+    data_out: bool,
+    state: EepromState,
+    shift: u16,
+    bits: u8,
+    addr: u8,
+    write_enabled: bool,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Self {
+            cs: false,
+            clk: false,
+            di: false,
+            data_out: true,
+            state: EepromState::Idle,
+            shift: 0,
+            bits: 0,
+            addr: 0,
+            write_enabled: false,
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.cs);
+        w.bool(self.clk);
+        w.bool(self.di);
+        w.bool(self.data_out);
+        w.u8(self.state as u8);
+        w.u16(self.shift);
+        w.u8(self.bits);
+        w.u8(self.addr);
+        w.bool(self.write_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.cs = r.bool()?;
+        self.clk = r.bool()?;
+        self.di = r.bool()?;
+        self.data_out = r.bool()?;
+        self.state = EepromState::from_u8(r.u8()?)?;
+        self.shift = r.u16()?;
+        self.bits = r.u8()?;
+        self.addr = r.u8()?;
+        self.write_enabled = r.bool()?;
+        Ok(())
+    }
+
+    fn word_offset(addr: u8, storage: &[u8]) -> usize {
+        (addr as usize * 2) % storage.len()
+    }
+
+    fn read_word(addr: u8, storage: &[u8]) -> u16 {
+        let i = Self::word_offset(addr, storage);
+        ((storage[i] as u16) << 8) | storage[i + 1] as u16
+    }
+
+    // Returns true if the stored word changed
+    fn write_word(addr: u8, value: u16, storage: &mut [u8]) -> bool {
+        let i = Self::word_offset(addr, storage);
+        let old = Self::read_word(addr, storage);
+        storage[i] = (value >> 8) as u8;
+        storage[i + 1] = value as u8;
+        old != value
+    }
+
+    fn pins(&self) -> u8 {
+        let mut pins = 0;
+        if self.cs {
+            pins |= EEPROM_CS;
+        }
+        if self.clk {
+            pins |= EEPROM_CLK;
+        }
+        if self.di {
+            pins |= EEPROM_DI;
+        }
+        if self.data_out {
+            pins |= EEPROM_DO;
+        }
+        pins
+    }
+
+    // Returns true if the EEPROM contents changed
+    fn write_pins(&mut self, value: u8, storage: &mut [u8]) -> bool {
+        let cs = value & EEPROM_CS != 0;
+        let clk = value & EEPROM_CLK != 0;
+        let rising_clk = clk && !self.clk;
+        let selected = cs && self.cs;
+        self.cs = cs;
+        self.clk = clk;
+        self.di = value & EEPROM_DI != 0;
+
+        if !cs {
+            // Deselecting aborts anything in progress, DO reports ready
+            self.state = EepromState::Idle;
+            self.data_out = true;
+            return false;
+        }
+        if !selected || !rising_clk || storage.is_empty() {
+            return false;
+        }
+
+        let bit = self.di as u16;
+        match self.state {
+            EepromState::Idle => {
+                // Wait for the start bit
+                if self.di {
+                    self.state = EepromState::Command;
+                    self.shift = 0;
+                    self.bits = 0;
+                }
+            }
+            EepromState::Command => {
+                self.shift = (self.shift << 1) | bit;
+                self.bits += 1;
+                // 2 opcode bits followed by 8 address bits
+                if self.bits == 10 {
+                    return self.run_command(storage);
+                }
+            }
+            EepromState::Read => {
+                self.data_out = self.shift & 0x8000 != 0;
+                self.shift <<= 1;
+                self.bits += 1;
+                if self.bits == 16 {
+                    // Holding CS keeps reading sequential words
+                    self.addr = (self.addr + 1) & 0x7F;
+                    self.shift = Self::read_word(self.addr, storage);
+                    self.bits = 0;
+                }
+            }
+            EepromState::Write | EepromState::WriteAll => {
+                self.shift = (self.shift << 1) | bit;
+                self.bits += 1;
+                if self.bits == 16 {
+                    let mut changed = false;
+                    if self.write_enabled {
+                        if self.state == EepromState::Write {
+                            changed = Self::write_word(self.addr, self.shift, storage);
+                        } else {
+                            for addr in 0..(storage.len() / 2) as u8 {
+                                changed |= Self::write_word(addr, self.shift, storage);
+                            }
+                        }
+                    }
+                    self.state = EepromState::Idle;
+                    self.data_out = true;
+                    return changed;
+                }
+            }
+        }
+        false
+    }
+
+    fn run_command(&mut self, storage: &mut [u8]) -> bool {
+        let opcode = (self.shift >> 8) & 0b11;
+        let addr = self.shift as u8;
+        self.addr = addr & 0x7F;
+        self.bits = 0;
+        self.state = EepromState::Idle;
+
+        match opcode {
+            0b10 => {
+                // A dummy zero bit comes out before the data
+                self.state = EepromState::Read;
+                self.shift = Self::read_word(self.addr, storage);
+                self.data_out = false;
+            }
+            0b01 => self.state = EepromState::Write,
+            0b11 => {
+                if self.write_enabled {
+                    return Self::write_word(self.addr, 0xFFFF, storage);
+                }
+            }
+            _ => match addr >> 6 {
+                0b00 => self.write_enabled = false,
+                0b11 => self.write_enabled = true,
+                0b10 => {
+                    if self.write_enabled {
+                        let changed = storage.iter().any(|&b| b != 0xFF);
+                        storage.fill(0xFF);
+                        return changed;
+                    }
+                }
+                _ => self.state = EepromState::WriteAll,
+            },
+        }
+        false
+    }
+}
+
+pub struct Mbc7 {
+    ram_enabled: bool,
+    ram_enabled_2: bool,
+    pub(super) rom_bank: u8,
+    // Tilt in g as last reported by the frontend, positive is right and down
+    tilt_x: f32,
+    tilt_y: f32,
+    accel_x: u16,
+    accel_y: u16,
+    latch_ready: bool,
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new() -> Self {
+        Self {
+            ram_enabled: false,
+            ram_enabled_2: false,
+            rom_bank: 1,
+            tilt_x: 0.0,
+            tilt_y: 0.0,
+            accel_x: ACCEL_ERASED,
+            accel_y: ACCEL_ERASED,
+            latch_ready: false,
+            eeprom: Eeprom::new(),
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.ram_enabled);
+        w.bool(self.ram_enabled_2);
+        w.u8(self.rom_bank);
+        w.f32(self.tilt_x);
+        w.f32(self.tilt_y);
+        w.u16(self.accel_x);
+        w.u16(self.accel_y);
+        w.bool(self.latch_ready);
+        self.eeprom.save_state(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ram_enabled = r.bool()?;
+        self.ram_enabled_2 = r.bool()?;
+        self.rom_bank = r.u8()?;
+        self.tilt_x = r.f32()?;
+        self.tilt_y = r.f32()?;
+        self.accel_x = r.u16()?;
+        self.accel_y = r.u16()?;
+        self.latch_ready = r.bool()?;
+        self.eeprom.load_state(r)
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt_x = x.clamp(-2.0, 2.0);
+        self.tilt_y = y.clamp(-2.0, 2.0);
+    }
+
+    fn registers_enabled(&self, addr: u16) -> bool {
+        self.ram_enabled && self.ram_enabled_2 && addr < 0xB000
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        if !self.registers_enabled(addr) {
+            return 0xFF;
+        }
+        match (addr >> 4) & 0x0F {
+            0x2 => self.accel_x as u8,
+            0x3 => (self.accel_x >> 8) as u8,
+            0x4 => self.accel_y as u8,
+            0x5 => (self.accel_y >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.pins(),
+            _ => 0xFF,
+        }
+    }
+
+    // Returns true if the EEPROM contents changed
+    pub fn write_register(&mut self, value: u8, addr: u16, storage: &mut [u8]) -> bool {
+        if !self.registers_enabled(addr) {
+            return false;
+        }
+        match (addr >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.accel_x = ACCEL_ERASED;
+                self.accel_y = ACCEL_ERASED;
+                self.latch_ready = true;
+            }
+            0x1 if value == 0xAA && self.latch_ready => {
+                // The X axis reads lower as the cart tilts right
+                self.accel_x = (ACCEL_CENTER - self.tilt_x * ACCEL_ONE_G) as u16;
+                self.accel_y = (ACCEL_CENTER + self.tilt_y * ACCEL_ONE_G) as u16;
+                self.latch_ready = false;
+            }
+            0x8 => return self.eeprom.write_pins(value, storage),
+            _ => (),
+        }
+        false
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_enabled_2 = value == 0x40,
+            _ => (),
+        }
+    }
+}
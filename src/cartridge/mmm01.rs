@@ -0,0 +1,141 @@
+// Reference Manual - https://gbdev.io/pandocs/MMM01.html
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+
+const MENU_SIZE: usize = 0x8000;
+
+// Offset of the menu, whose header describes the whole cart, when the image is an MMM01 multicart
+pub fn menu_offset(rom: &[u8]) -> Option<usize> {
+    let start = rom.len().checked_sub(MENU_SIZE)?;
+    if start > 0 && matches!(rom[start + 0x0147], 0x0B..=0x0D) {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+pub struct Mmm01 {
+    // Once mapped, the outer bank bits are locked and the game sees an MBC1-like mapper
+    mapped: bool,
+    pub(super) ram_enabled: bool,
+    rom_bank: u16,
+    // Bits 1-4 of the ROM bank number the game is not allowed to change
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_mask: u8,
+    mbc1_mode: bool,
+    mode_locked: bool,
+}
+
+impl Mmm01 {
+    pub fn new() -> Self {
+        Self {
+            mapped: false,
+            ram_enabled: false,
+            rom_bank: 0,
+            rom_bank_mask: 0,
+            ram_bank: 0,
+            ram_bank_mask: 0,
+            mbc1_mode: false,
+            mode_locked: false,
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.mapped);
+        w.bool(self.ram_enabled);
+        w.u16(self.rom_bank);
+        w.u8(self.rom_bank_mask);
+        w.u8(self.ram_bank);
+        w.u8(self.ram_bank_mask);
+        w.bool(self.mbc1_mode);
+        w.bool(self.mode_locked);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.mapped = r.bool()?;
+        self.ram_enabled = r.bool()?;
+        self.rom_bank = r.u16()?;
+        self.rom_bank_mask = r.u8()?;
+        self.ram_bank = r.u8()?;
+        self.ram_bank_mask = r.u8()?;
+        self.mbc1_mode = r.bool()?;
+        self.mode_locked = r.bool()?;
+        Ok(())
+    }
+
+    fn game_rom_bits(&self) -> u16 {
+        0x1F & !((self.rom_bank_mask as u16) << 1)
+    }
+
+    fn game_ram_bits(&self) -> u8 {
+        0b11 & !self.ram_bank_mask
+    }
+
+    // Before mapping, the menu in the last 32KB is visible regardless of the registers
+    pub fn low_rom_bank(&self, rom_banks: usize) -> usize {
+        if !self.mapped {
+            return rom_banks.saturating_sub(2);
+        }
+        (self.rom_bank & !self.game_rom_bits()) as usize
+    }
+
+    pub fn high_rom_bank(&self, rom_banks: usize) -> usize {
+        if !self.mapped {
+            return rom_banks.saturating_sub(1);
+        }
+        if self.rom_bank & self.game_rom_bits() == 0 {
+            (self.rom_bank | 1) as usize
+        } else {
+            self.rom_bank as usize
+        }
+    }
+
+    // As on MBC1, mode 0 pins the bits the game controls to zero
+    pub fn ram_bank(&self) -> usize {
+        if self.mapped && !self.mbc1_mode {
+            (self.ram_bank & !self.game_ram_bits()) as usize
+        } else {
+            self.ram_bank as usize
+        }
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if !self.mapped {
+                    self.ram_bank_mask = (value >> 4) & 0b11;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3FFF => {
+                if self.mapped {
+                    let bits = self.game_rom_bits();
+                    self.rom_bank = (self.rom_bank & !bits) | (value as u16 & bits);
+                } else {
+                    self.rom_bank = (self.rom_bank & !0x7F) | (value as u16 & 0x7F);
+                }
+            }
+            0x4000..=0x5FFF => {
+                if self.mapped {
+                    let bits = self.game_ram_bits();
+                    self.ram_bank = (self.ram_bank & !bits) | (value & bits);
+                } else {
+                    self.ram_bank = value & 0x0F;
+                    self.rom_bank = (self.rom_bank & 0x7F) | (((value as u16 >> 4) & 0b11) << 7);
+                    self.mode_locked = value & 0x40 != 0;
+                }
+            }
+            0x6000..=0x7FFF => {
+                if !(self.mapped && self.mode_locked) {
+                    self.mbc1_mode = value & 1 != 0;
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 2) & 0x0F;
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// Command-line options for the frontend, parsed by hand to keep the dependency list short
+use minifb::Scale;
+use my_emulator::Model;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: my-emulator [OPTIONS] <ROM>
+
+Options:
+  --scale <N>          Window scale: 1, 2, 4, 8, 16, 32 or fit [default: 2]
+  --palette <NAME>     Screen colours: blue, gray, green, or four comma-separated
+                       hex colours from lightest to darkest [default: blue]
+  --boot-rom <PATH>    Boot ROM to run before the cartridge
+  --model <MODEL>      dmg, mgb or cgb, whose post-boot state to start in when there is
+                       no boot ROM [default: dmg]
+  --headless           Run without a window and print the emulation speed
+  --frames <N>         Stop after N frames [default with --headless: 3600]
+  --save-dir <DIR>     Where battery saves and save state slots go [default: next to the ROM]
+  --fps <N>            Frame rate cap for the window [default: 60]
+  --uncapped           Run the window as fast as possible
+  --mute               Don't play sound
+  --link <LINK>        What the link cable connects to: stdout, loopback,
+                       tcp-listen:<ADDR>, tcp:<ADDR>, unix-listen:<PATH> or unix:<PATH>
+  --trace              Log every executed instruction to stderr
+  -h, --help           Print this help";
+
+const DEFAULT_HEADLESS_FRAMES: u32 = 3600;
+
+const PALETTES: [(&str, [u32; 4]); 3] = [
+    ("blue", [0xFFFFFF, 0x9BB7FF, 0x4863A0, 0x0A0A40]),
+    ("gray", [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000]),
+    ("green", [0xE0F8D0, 0x88C070, 0x346856, 0x081820]),
+];
+
+// The other end of the link cable
+pub enum Link {
+    Stdout,
+    Loopback,
+    TcpListen(String),
+    Tcp(String),
+    UnixListen(PathBuf),
+    Unix(PathBuf),
+}
+
+pub struct Options {
+    pub rom_path: String,
+    pub scale: Scale,
+    pub palette: [u32; 4],
+    pub boot_rom: Option<PathBuf>,
+    pub model: Model,
+    pub headless: bool,
+    pub frames: Option<u32>,
+    pub save_dir: Option<PathBuf>,
+    // 0 leaves the frame rate uncapped
+    pub fps: usize,
+    // Builds without the audio feature are always silent
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    pub mute: bool,
+    pub trace: bool,
+    pub link: Option<Link>,
+}
+
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut rom_path = None;
+    let mut scale = Scale::X2;
+    let mut palette = PALETTES[0].1;
+    let mut boot_rom = None;
+    let mut model = Model::Dmg;
+    let mut headless = false;
+    let mut frames = None;
+    let mut save_dir = None;
+    let mut fps = 60;
+    let mut mute = false;
+    let mut trace = false;
+    let mut link = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--scale" => scale = parse_scale(&value()?)?,
+            "--palette" => palette = parse_palette(&value()?)?,
+            "--boot-rom" => boot_rom = Some(PathBuf::from(value()?)),
+            "--model" => model = parse_model(&value()?)?,
+            "--headless" => headless = true,
+            "--frames" => frames = Some(parse_number(&arg, &value()?)?),
+            "--save-dir" => save_dir = Some(PathBuf::from(value()?)),
+            "--fps" => match parse_number(&arg, &value()?)? {
+                0 => {
+                    return Err("--fps must be at least 1, use --uncapped to remove the cap".into())
+                }
+                n => fps = n as usize,
+            },
+            "--uncapped" => fps = 0,
+            "--mute" => mute = true,
+            "--trace" => trace = true,
+            "--link" => link = Some(parse_link(&value()?)?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ if rom_path.is_some() => return Err(format!("unexpected argument {arg}")),
+            _ => rom_path = Some(arg),
+        }
+    }
+
+    let Some(rom_path) = rom_path else {
+        return Err("no ROM given".to_string());
+    };
+    if headless && frames.is_none() {
+        frames = Some(DEFAULT_HEADLESS_FRAMES);
+    }
+
+    Ok(Command::Run(Options {
+        rom_path,
+        scale,
+        palette,
+        boot_rom,
+        model,
+        headless,
+        frames,
+        save_dir,
+        fps,
+        mute,
+        trace,
+        link,
+    }))
+}
+
+fn parse_number(option: &str, value: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{option} expects a whole number, got {value}"))
+}
+
+fn parse_scale(value: &str) -> Result<Scale, String> {
+    match value {
+        "1" => Ok(Scale::X1),
+        "2" => Ok(Scale::X2),
+        "4" => Ok(Scale::X4),
+        "8" => Ok(Scale::X8),
+        "16" => Ok(Scale::X16),
+        "32" => Ok(Scale::X32),
+        "fit" => Ok(Scale::FitScreen),
+        _ => Err(format!(
+            "--scale must be 1, 2, 4, 8, 16, 32 or fit, got {value}"
+        )),
+    }
+}
+
+fn parse_model(value: &str) -> Result<Model, String> {
+    match value {
+        "dmg" => Ok(Model::Dmg),
+        "mgb" => Ok(Model::Mgb),
+        "cgb" => Ok(Model::Cgb),
+        _ => Err(format!("--model must be dmg, mgb or cgb, got {value}")),
+    }
+}
+
+fn parse_link(value: &str) -> Result<Link, String> {
+    let link = match value.split_once(':') {
+        _ if value == "stdout" => Link::Stdout,
+        _ if value == "loopback" => Link::Loopback,
+        Some(("tcp-listen", addr)) => Link::TcpListen(addr.to_string()),
+        Some(("tcp", addr)) => Link::Tcp(addr.to_string()),
+        Some(("unix-listen", path)) => Link::UnixListen(PathBuf::from(path)),
+        Some(("unix", path)) => Link::Unix(PathBuf::from(path)),
+        _ => {
+            return Err(format!(
+                "--link must be stdout, loopback, tcp-listen:<ADDR>, tcp:<ADDR>, unix-listen:<PATH> or unix:<PATH>, got {value}"
+            ));
+        }
+    };
+    Ok(link)
+}
+
+fn parse_palette(value: &str) -> Result<[u32; 4], String> {
+    if let Some((_, shades)) = PALETTES.iter().find(|(name, _)| *name == value) {
+        return Ok(*shades);
+    }
+
+    let colours = value
+        .split(',')
+        .map(|hex| {
+            u32::from_str_radix(hex.trim_start_matches('#'), 16)
+                .ok()
+                .filter(|&c| c <= 0xFFFFFF)
+        })
+        .collect::<Option<Vec<_>>>();
+    match colours.as_deref() {
+        Some(&[c0, c1, c2, c3]) => Ok([c0, c1, c2, c3]),
+        _ => Err(format!(
+            "--palette must be blue, gray, green or four hex colours like e0f8d0,88c070,346856,081820, got {value}"
+        )),
+    }
+}
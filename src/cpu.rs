@@ -1,66 +1,17 @@
 use crate::instructions::*;
-use crate::logger::*;
+use crate::opcodes::{disassemble, CB_OPCODES, OPCODES};
 use crate::state::{GameState, Register, CC};
-use std::collections::HashSet;
 
 type InstructionWrapper = fn(&mut GameState) -> u8;
 
 pub struct CPU {
     non_prefix_opcodes: [InstructionWrapper; 256],
     cb_prefix_opcodes: [InstructionWrapper; 256],
-    two_byte_ins: HashSet<u8>,
-    three_byte_ins: HashSet<u8>,
+    trace: bool,
 }
 
 impl CPU {
     pub fn initialize() -> Self {
-        let mut iibi: HashSet<u8> = HashSet::new();
-        iibi.insert(0x06);
-        iibi.insert(0x0E);
-        iibi.insert(0x10);
-        iibi.insert(0x16);
-        iibi.insert(0x18);
-        iibi.insert(0x1E);
-        iibi.insert(0x20);
-        iibi.insert(0x26);
-        iibi.insert(0x28);
-        iibi.insert(0x2E);
-        iibi.insert(0x30);
-        iibi.insert(0x36);
-        iibi.insert(0x38);
-        iibi.insert(0x3E);
-        iibi.insert(0xC6);
-        iibi.insert(0xCE);
-        iibi.insert(0xD6);
-        iibi.insert(0xDE);
-        iibi.insert(0xE0);
-        iibi.insert(0xE6);
-        iibi.insert(0xE8);
-        iibi.insert(0xEE);
-        iibi.insert(0xF0);
-        iibi.insert(0xF6);
-        iibi.insert(0xF8);
-        iibi.insert(0xFE);
-
-        let mut iiibi: HashSet<u8> = HashSet::new();
-        iiibi.insert(0x01);
-        iiibi.insert(0x08);
-        iiibi.insert(0x11);
-        iiibi.insert(0x21);
-        iiibi.insert(0x31);
-        iiibi.insert(0xC2);
-        iiibi.insert(0xC3);
-        iiibi.insert(0xC4);
-        iiibi.insert(0xCA);
-        iiibi.insert(0xCC);
-        iiibi.insert(0xCD);
-        iiibi.insert(0xD2);
-        iiibi.insert(0xD4);
-        iiibi.insert(0xDA);
-        iiibi.insert(0xDC);
-        iiibi.insert(0xEA);
-        iiibi.insert(0xFA);
-
         Self {
             non_prefix_opcodes: [
                 |_: &mut GameState| 1,                                     // 0x00
@@ -580,92 +531,105 @@ impl CPU {
                 |s: &mut GameState| set_u3_r8(s, 7, Register::A), // 0xFF
             ],
 
-            two_byte_ins: iibi,
-
-            three_byte_ins: iiibi,
+            trace: false,
         }
     }
 
+    // Logs every instruction to stderr before it runs
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
     pub fn step(&self, game_state: &mut GameState) -> u8 {
-        let mut interrupted = false;
-        if game_state.get_interrupts() && (game_state.get_i_flag() & game_state.get_i_enable() != 0)
-        {
-            interrupted = interrupt_handler(game_state);
+        if game_state.stopped() {
+            // The oscillator is off, so nothing ticks until a joypad line is pulled low
+            if !game_state.joypad_line_low() {
+                return 4;
+            }
+            game_state.set_stopped(false);
         }
 
-        let curr_pc = game_state.get_register16(Register::PC);
-        let next_instruction = game_state.read(curr_pc);
-        // print!(
-        //     "PC: 0x{:04X}, OP: 0x{:02X}, SP: 0x{:04X}",
-        //     curr_pc,
-        //     next_instruction,
-        //     game_state.get_register16(Register::SP)
-        // );
-        let cycles;
+        game_state.begin_instruction();
 
-        let mut advance_amount = 1;
-        if next_instruction != 0xCB {
-            if self.two_byte_ins.contains(&next_instruction) {
-                advance_amount = 2;
-                // print!(", Byte: 0x{:02X},        ", game_state.read(curr_pc + 1));
-            } else if self.three_byte_ins.contains(&next_instruction) {
-                advance_amount = 3;
-                // print!(
-                //     ", Bytes: 0x{:02X}, 0x{:02X}, ",
-                //     game_state.read(curr_pc + 1),
-                //     game_state.read(curr_pc + 2)
-                // );
-            } else {
-                // print!("                     ");
+        if game_state.halted() {
+            if !game_state.interrupt_pending() {
+                // Time keeps passing one M-cycle at a time until an interrupt is requested
+                game_state.tick();
+                return 4;
             }
-            cycles = (self.non_prefix_opcodes[next_instruction as usize])(game_state);
-            // print!(" INS: {}", non_prefix_strings[next_instruction as usize]);
-        } else {
-            // print!(", Code: 0x{:02X} ", game_state.read(curr_pc + 1));
-            let actual_ins = game_state.read(curr_pc + 1);
-            cycles = (self.cb_prefix_opcodes[actual_ins as usize])(game_state);
-            advance_amount = 2;
-            // print!(", INS: {}", cb_prefix_strings[next_instruction as usize]);
+            game_state.set_halted(false);
         }
-        // println!();
 
-        if game_state.pc_moved() {
-            advance_amount = 0;
-            game_state.set_pc_moved(false);
+        // Dispatching an interrupt replaces the fetch and takes 5 M-cycles of its own
+        if game_state.get_interrupts() && game_state.interrupt_pending() {
+            let cycles = interrupt_handler(game_state);
+            game_state.end_instruction(cycles);
+            return 4 * cycles;
         }
 
-        let cycles = cycles + if interrupted { 5 } else { 0 };
+        // EI takes effect after the instruction that follows it, so IME is set before that one runs
+        // and the earliest dispatch is on the step after
+        if game_state.ei_pending() {
+            game_state.set_ei_pending(false);
+            game_state.set_interrupts(true);
+        }
+
+        let mut curr_pc = game_state.get_register16(Register::PC);
+        if self.trace {
+            trace_instruction(game_state, curr_pc);
+        }
+        let next_instruction = game_state.cpu_read(curr_pc);
+        if game_state.halt_bug() {
+            // PC failed to increment past this opcode, so it runs with its first byte read again
+            // as an operand. Backing PC up by one gets the same effect from the usual advance
+            game_state.set_halt_bug(false);
+            curr_pc = curr_pc.wrapping_sub(1);
+            game_state.set_register16(Register::PC, curr_pc);
+        }
 
-        let t_cycles = 4 * cycles;
+        let (info, cycles) = if next_instruction != 0xCB {
+            let cycles = (self.non_prefix_opcodes[next_instruction as usize])(game_state);
+            (&OPCODES[next_instruction as usize], cycles)
+        } else {
+            let actual_ins = game_state.cpu_read(curr_pc.wrapping_add(1));
+            let cycles = (self.cb_prefix_opcodes[actual_ins as usize])(game_state);
+            (&CB_OPCODES[actual_ins as usize], cycles)
+        };
+        debug_assert!(
+            cycles == info.cycles || cycles == info.taken_cycles,
+            "{} took {cycles} M-cycles",
+            info.mnemonic
+        );
 
-        game_state.update_clock(t_cycles);
+        let mut advance_amount = info.length as u16;
+        if game_state.pc_moved() {
+            advance_amount = 0;
+            game_state.set_pc_moved(false);
+        }
 
         // it is possible for curr_pc and Register::PC to disagree at this point
         game_state.set_register16(
             Register::PC,
-            game_state.get_register16(Register::PC) + advance_amount,
+            game_state
+                .get_register16(Register::PC)
+                .wrapping_add(advance_amount),
         );
 
-        game_state.inc_div(t_cycles);
+        game_state.end_instruction(cycles);
 
-        if game_state.get_tac() & 0b100 != 0 {
-            let tima_update_freq = match game_state.get_tac() & 0b11 {
-                0b00 => 1024,
-                0b01 => 16,
-                0b10 => 64,
-                0b11 => 256,
-                _ => unreachable!(),
-            };
-
-            game_state.inc_available_cycles(t_cycles as u16);
-            let mut available_cycles = game_state.get_available_cycles();
-            while available_cycles >= tima_update_freq {
-                game_state.dec_available_cycles(tima_update_freq);
-                available_cycles -= tima_update_freq;
-                game_state.update_tima();
-            }
-        }
-
-        t_cycles
+        4 * cycles
     }
 }
+
+fn trace_instruction(game_state: &GameState, pc: u16) {
+    let (text, _) = disassemble(game_state, pc);
+    eprintln!(
+        "{pc:04X}  {text:<20} A:{:02X} F:{:02X} BC:{:04X} DE:{:04X} HL:{:04X} SP:{:04X}",
+        game_state.get_register8(Register::A),
+        game_state.get_register8(Register::F),
+        game_state.get_register16(Register::BC),
+        game_state.get_register16(Register::DE),
+        game_state.get_register16(Register::HL),
+        game_state.get_register16(Register::SP),
+    );
+}
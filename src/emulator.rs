@@ -0,0 +1,211 @@
+use crate::boot::{BootRom, Model};
+use crate::cartridge::{CartridgeHeader, ImageSource};
+use crate::constants::{DOTS_PER_SL, MAX_SL};
+use crate::cpu::CPU;
+use crate::opcodes::disassemble;
+use crate::rtc::TimeSource;
+use crate::savestate::{StateReader, StateWriter};
+use crate::serial::SerialLink;
+use crate::state::{GameState, Register};
+use std::io::Result;
+use std::path::Path;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+const CYCLES_PER_FRAME: u32 = DOTS_PER_SL as u32 * (MAX_SL as u32 + 1);
+
+#[derive(Clone, Copy, Default)]
+pub struct Buttons {
+    pub a: bool,
+    pub b: bool,
+    pub start: bool,
+    pub select: bool,
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+// A snapshot of the CPU registers, for debuggers and test harnesses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+pub struct Emulator {
+    game_state: GameState,
+    cpu: CPU,
+}
+
+impl Emulator {
+    // Runs a ROM image with no save file behind it
+    pub fn load_rom(rom: Vec<u8>) -> Result<Self> {
+        Ok(Self::with_state(GameState::from_rom(rom)?))
+    }
+
+    // Runs a ROM file, picking up the battery save next to it which flush_save writes back to
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::with_state(GameState::start_game(path)?))
+    }
+
+    // Like open, but keeps the battery save in save_dir, named after the ROM
+    pub fn open_with_save_dir(path: &str, save_dir: &Path) -> Result<Self> {
+        let file_name = Path::new(path).file_name().unwrap_or_default();
+        let save_path = save_dir.join(file_name).with_extension("sav");
+        Ok(Self::with_state(GameState::start_game_saving_to(
+            path, save_path,
+        )?))
+    }
+
+    fn with_state(game_state: GameState) -> Self {
+        Self {
+            game_state,
+            cpu: CPU::initialize(),
+        }
+    }
+
+    // Starts over as model would be once its boot ROM has handed over to the cartridge, which is
+    // also how a freshly loaded ROM starts, as a DMG
+    pub fn restart(&mut self, model: Model) {
+        self.game_state.restart(model);
+    }
+
+    // Starts over from power-on and runs a 256 byte DMG/MGB or 2304 byte CGB boot ROM first
+    pub fn boot(&mut self, boot_rom: Vec<u8>) -> Result<()> {
+        self.game_state.boot(BootRom::new(boot_rom)?);
+        Ok(())
+    }
+
+    pub fn cartridge_header(&self) -> &CartridgeHeader {
+        self.game_state.cartridge_header()
+    }
+
+    // Runs until the PPU finishes a frame. With the LCD stopped it gives up after a frame's
+    // worth of cycles instead, so callers can keep pacing on it
+    pub fn run_frame(&mut self) {
+        let mut cycles = 0;
+        while cycles < CYCLES_PER_FRAME {
+            cycles += self.step_instruction();
+            if self.game_state.take_frame_ready() {
+                break;
+            }
+        }
+    }
+
+    // Runs one instruction, interrupt dispatch or halted M-cycle and returns the T-cycles it took
+    pub fn step_instruction(&mut self) -> u32 {
+        self.cpu.step(&mut self.game_state) as u32
+    }
+
+    // SCREEN_WIDTH * SCREEN_HEIGHT pixels as 0RGB, row by row
+    pub fn framebuffer(&self) -> &[u32] {
+        self.game_state.framebuffer()
+    }
+
+    // Colours for the four DMG shades, lightest first, as 0RGB
+    pub fn set_palette(&mut self, shades: [u32; 4]) {
+        self.game_state.set_shades(shades);
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.game_state.update_joypad(
+            buttons.a,
+            buttons.b,
+            buttons.start,
+            buttons.select,
+            buttons.up,
+            buttons.down,
+            buttons.left,
+            buttons.right,
+        );
+    }
+
+    // Accelerometer input for MBC7 carts, in g with positive x to the right and y down
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.game_state.set_tilt(x, y);
+    }
+
+    // Interleaved stereo samples in -1.0..=1.0 generated since the last call
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        self.game_state.drain_audio_samples()
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.game_state.set_audio_sample_rate(sample_rate);
+    }
+
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.game_state.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    // A state that turns out to be corrupt partway through leaves the machine as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<()> {
+        self.game_state.load_state(&mut StateReader::new(data))
+    }
+
+    // Persists battery-backed cartridge RAM if it changed, for ROMs loaded with open
+    pub fn flush_save(&mut self) -> Result<()> {
+        self.game_state.flush_save()
+    }
+
+    pub fn registers(&self) -> CpuRegisters {
+        let gs = &self.game_state;
+        CpuRegisters {
+            a: gs.get_register8(Register::A),
+            f: gs.get_register8(Register::F),
+            b: gs.get_register8(Register::B),
+            c: gs.get_register8(Register::C),
+            d: gs.get_register8(Register::D),
+            e: gs.get_register8(Register::E),
+            h: gs.get_register8(Register::H),
+            l: gs.get_register8(Register::L),
+            sp: gs.get_register16(Register::SP),
+            pc: gs.get_register16(Register::PC),
+        }
+    }
+
+    // Reads memory as the CPU would see it, without taking any time
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.game_state.read(addr)
+    }
+
+    // The instruction at addr in rgbds syntax, along with its length in bytes
+    pub fn disassemble(&self, addr: u16) -> (String, u8) {
+        disassemble(&self.game_state, addr)
+    }
+
+    // Logs every instruction to stderr before it runs
+    pub fn set_trace(&mut self, trace: bool) {
+        self.cpu.set_trace(trace);
+    }
+
+    // What's on the other end of the link cable, by default nothing
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.game_state.set_serial_link(link);
+    }
+
+    // Called with the new motor state whenever a rumble cartridge turns its motor on or off
+    pub fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+        self.game_state.set_rumble_callback(callback);
+    }
+
+    pub fn set_rtc_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.game_state.set_rtc_time_source(time_source);
+    }
+
+    pub fn set_camera_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.game_state.set_camera_image_source(source);
+    }
+}
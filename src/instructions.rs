@@ -3,6 +3,15 @@
 use crate::constants::{INT_JOYPAD, INT_LCD, INT_SERIAL, INT_TIMER, INT_VBLANK};
 use crate::state::{Flags, GameState, Register, CC};
 
+// In priority order
+const INTERRUPT_VECTORS: [(u8, u16); 5] = [
+    (INT_VBLANK, 0x0040),
+    (INT_LCD, 0x0048),
+    (INT_TIMER, 0x0050),
+    (INT_SERIAL, 0x0058),
+    (INT_JOYPAD, 0x0060),
+];
+
 // Load instructions
 pub fn ld_r8_r8(game_state: &mut GameState, r1: Register, r2: Register) -> u8 {
     game_state.set_register8(r1, game_state.get_register8(r2));
@@ -10,24 +19,24 @@ pub fn ld_r8_r8(game_state: &mut GameState, r1: Register, r2: Register) -> u8 {
 }
 
 pub fn ld_r8_n8(game_state: &mut GameState, r1: Register) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     game_state.set_register8(r1, val);
     2
 }
 
 pub fn ld_r16_n16(game_state: &mut GameState, r1: Register) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let val = ((msb as u16) << 8) | (lsb as u16);
     // if matches!(r1, Register::HL) { println!("Wrote 0x{:04X} to register HL", val); }
     game_state.set_register16(r1, val);
     // if matches!(r1, Register::HL) { println!("New value of HL: 0x{:04X}", game_state.get_register16(r1)); }
-    2
+    3
 }
 
 pub fn ld_hladdr_r8(game_state: &mut GameState, r: Register) -> u8 {
     // println!("Writing to addr: 0x{:04X}", game_state.get_register16(Register::HL));
-    game_state.write(
+    game_state.cpu_write(
         game_state.get_register8(r),
         game_state.get_register16(Register::HL),
     );
@@ -36,20 +45,21 @@ pub fn ld_hladdr_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn ld_hladdr_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
-    game_state.write(val, game_state.get_register16(Register::HL));
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    game_state.cpu_write(val, game_state.get_register16(Register::HL));
 
     3
 }
 
 pub fn ld_r8_hladdr(game_state: &mut GameState, r: Register) -> u8 {
-    game_state.set_register8(r, game_state.read(game_state.get_register16(Register::HL)));
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    game_state.set_register8(r, val);
 
     2
 }
 
 pub fn ld_r16addr_a(game_state: &mut GameState, r: Register) -> u8 {
-    game_state.write(
+    game_state.cpu_write(
         game_state.get_register8(Register::A),
         game_state.get_register16(r),
     );
@@ -58,24 +68,24 @@ pub fn ld_r16addr_a(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn ld_n16addr_a(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let addr = ((msb as u16) << 8) | (lsb as u16);
-    game_state.write(game_state.get_register8(Register::A), addr);
+    game_state.cpu_write(game_state.get_register8(Register::A), addr);
 
     4
 }
 
 pub fn ldh_n8addr_a(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     let addr = 0xFF00 | (lsb as u16);
-    game_state.write(game_state.get_register8(Register::A), addr);
+    game_state.cpu_write(game_state.get_register8(Register::A), addr);
 
     3
 }
 
 pub fn ldh_caddr_a(game_state: &mut GameState) -> u8 {
-    game_state.write(
+    game_state.cpu_write(
         game_state.get_register8(Register::A),
         0xFF00 + game_state.get_register8(Register::C) as u16,
     );
@@ -84,33 +94,34 @@ pub fn ldh_caddr_a(game_state: &mut GameState) -> u8 {
 }
 
 pub fn ld_a_r16addr(game_state: &mut GameState, r: Register) -> u8 {
-    game_state.set_register8(Register::A, game_state.read(game_state.get_register16(r)));
+    let val = game_state.cpu_read(game_state.get_register16(r));
+    game_state.set_register8(Register::A, val);
 
     2
 }
 
 pub fn ld_a_n16addr(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let addr = ((msb as u16) << 8) | (lsb as u16);
-    game_state.set_register8(Register::A, game_state.read(addr));
+    let val = game_state.cpu_read(addr);
+    game_state.set_register8(Register::A, val);
     4
 }
 
 pub fn ldh_a_n8addr(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     let addr = 0xFF00 + (lsb as u16);
     if addr == 0xFF44 { // println!("LY: {}", game_state.get_ly());
     }
-    game_state.set_register8(Register::A, game_state.read(addr));
+    let val = game_state.cpu_read(addr);
+    game_state.set_register8(Register::A, val);
     3
 }
 
 pub fn ldh_a_caddr(game_state: &mut GameState) -> u8 {
-    game_state.set_register8(
-        Register::A,
-        game_state.read(game_state.get_register8(Register::C) as u16 + 0xFF00),
-    );
+    let val = game_state.cpu_read(game_state.get_register8(Register::C) as u16 + 0xFF00);
+    game_state.set_register8(Register::A, val);
 
     2
 }
@@ -195,16 +206,13 @@ pub fn adc_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn adc_a_hladdr(game_state: &mut GameState) -> u8 {
-    general_add_a_n8(
-        game_state,
-        game_state.read(game_state.get_register16(Register::HL)),
-        true,
-    );
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    general_add_a_n8(game_state, val, true);
     2
 }
 
 pub fn adc_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     general_add_a_n8(game_state, val, true);
     2
 }
@@ -215,16 +223,13 @@ pub fn add_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn add_a_hladdr(game_state: &mut GameState) -> u8 {
-    general_add_a_n8(
-        game_state,
-        game_state.read(game_state.get_register16(Register::HL)),
-        false,
-    );
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    general_add_a_n8(game_state, val, false);
     2
 }
 
 pub fn add_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     general_add_a_n8(game_state, val, false);
     2
 }
@@ -297,17 +302,13 @@ pub fn sbc_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn sbc_a_hladdr(game_state: &mut GameState) -> u8 {
-    general_sub_a_n8(
-        game_state,
-        game_state.read(game_state.get_register16(Register::HL)),
-        true,
-        false,
-    );
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    general_sub_a_n8(game_state, val, true, false);
     2
 }
 
 pub fn sbc_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     general_sub_a_n8(game_state, val, true, false);
     2
 }
@@ -318,17 +319,13 @@ pub fn sub_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn sub_a_hladdr(game_state: &mut GameState) -> u8 {
-    general_sub_a_n8(
-        game_state,
-        game_state.read(game_state.get_register16(Register::HL)),
-        false,
-        false,
-    );
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    general_sub_a_n8(game_state, val, false, false);
     2
 }
 
 pub fn sub_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     general_sub_a_n8(game_state, val, false, false);
     2
 }
@@ -340,17 +337,13 @@ pub fn cp_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 }
 
 pub fn cp_a_hladdr(game_state: &mut GameState) -> u8 {
-    general_sub_a_n8(
-        game_state,
-        game_state.read(game_state.get_register16(Register::HL)),
-        false,
-        true,
-    );
+    let val = game_state.cpu_read(game_state.get_register16(Register::HL));
+    general_sub_a_n8(game_state, val, false, true);
     2
 }
 
 pub fn cp_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     general_sub_a_n8(game_state, val, false, true);
     2
 }
@@ -376,8 +369,8 @@ pub fn dec_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn dec_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let (result, half_borrow, _) = sub8(game_state.read(addr), 1, 0);
-    game_state.write(result, addr);
+    let (result, half_borrow, _) = sub8(game_state.cpu_read(addr), 1, 0);
+    game_state.cpu_write(result, addr);
 
     let new_flags = Flags {
         Z: result == 0,
@@ -414,8 +407,8 @@ pub fn inc_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn inc_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let (result, half_carry, _) = add8(game_state.read(addr), 1, 0);
-    game_state.write(result, addr);
+    let (result, half_carry, _) = add8(game_state.cpu_read(addr), 1, 0);
+    game_state.cpu_write(result, addr);
 
     let new_flags = Flags {
         Z: result == 0,
@@ -455,13 +448,14 @@ pub fn and_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn and_a_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_and_a(game_state, game_state.read(addr));
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_and_a(game_state, val);
+    game_state.set_register8(Register::A, result);
     2
 }
 
 pub fn and_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     let result = general_and_a(game_state, val);
     game_state.set_register8(Register::A, result);
     2
@@ -505,13 +499,14 @@ pub fn or_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn or_a_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_or_a(game_state, game_state.read(addr), false);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_or_a(game_state, val, false);
+    game_state.set_register8(Register::A, result);
     2
 }
 
 pub fn or_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     let result = general_or_a(game_state, val, false);
     game_state.set_register8(Register::A, result);
     2
@@ -525,13 +520,14 @@ pub fn xor_a_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn xor_a_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_or_a(game_state, game_state.read(addr), true);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_or_a(game_state, val, true);
+    game_state.set_register8(Register::A, result);
     2
 }
 
 pub fn xor_a_n8(game_state: &mut GameState) -> u8 {
-    let val = game_state.read(game_state.get_register16(Register::PC) + 1);
+    let val = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
     let result = general_or_a(game_state, val, true);
     game_state.set_register8(Register::A, result);
     2
@@ -552,7 +548,7 @@ pub fn bit_u3_r8(game_state: &mut GameState, u: u8, r: Register) -> u8 {
 }
 
 pub fn bit_u3_hladdr(game_state: &mut GameState, u: u8) -> u8 {
-    let zero_flag = (game_state.read(game_state.get_register16(Register::HL)) & (1 << u)) == 0;
+    let zero_flag = (game_state.cpu_read(game_state.get_register16(Register::HL)) & (1 << u)) == 0;
     let new_flags = Flags {
         Z: zero_flag,
         N: false,
@@ -571,7 +567,8 @@ pub fn res_u3_r8(game_state: &mut GameState, u: u8, r: Register) -> u8 {
 
 pub fn res_u3_hladdr(game_state: &mut GameState, u: u8) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    game_state.write(game_state.read(addr) & !(1 << u), addr);
+    let val = game_state.cpu_read(addr);
+    game_state.cpu_write(val & !(1 << u), addr);
     4
 }
 
@@ -582,7 +579,8 @@ pub fn set_u3_r8(game_state: &mut GameState, u: u8, r: Register) -> u8 {
 
 pub fn set_u3_hladdr(game_state: &mut GameState, u: u8) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    game_state.write(game_state.read(addr) | (1 << u), addr);
+    let val = game_state.cpu_read(addr);
+    game_state.cpu_write(val | (1 << u), addr);
     4
 }
 
@@ -609,8 +607,9 @@ pub fn rl_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn rl_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_rl(game_state, game_state.read(addr), true);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_rl(game_state, val, true);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -641,8 +640,9 @@ pub fn rlc_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn rlc_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_rlc(game_state, game_state.read(addr), true);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_rlc(game_state, val, true);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -674,8 +674,9 @@ pub fn rr_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn rr_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_rr(game_state, game_state.read(addr), true);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_rr(game_state, val, true);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -706,8 +707,9 @@ pub fn rrc_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn rrc_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_rrc(game_state, game_state.read(addr), true);
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_rrc(game_state, val, true);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -738,8 +740,9 @@ pub fn sla_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn sla_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_sla(game_state, game_state.read(addr));
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_sla(game_state, val);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -765,8 +768,9 @@ pub fn sra_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn sra_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_sra(game_state, game_state.read(addr));
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_sra(game_state, val);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -791,8 +795,9 @@ pub fn srl_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn srl_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = general_srl(game_state, game_state.read(addr));
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = general_srl(game_state, val);
+    game_state.cpu_write(result, addr);
     4
 }
 
@@ -818,20 +823,26 @@ pub fn swap_r8(game_state: &mut GameState, r: Register) -> u8 {
 
 pub fn swap_hladdr(game_state: &mut GameState) -> u8 {
     let addr = game_state.get_register16(Register::HL);
-    let result = swap_general(game_state, game_state.read(addr));
-    game_state.write(result, addr);
+    let val = game_state.cpu_read(addr);
+    let result = swap_general(game_state, val);
+    game_state.cpu_write(result, addr);
     4
 }
 
 pub fn call_n16(game_state: &mut GameState) -> u8 {
+    let call_lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let call_msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let next_addr = game_state.get_register16(Register::PC) + 3;
     let lsb = (next_addr & 0x00FF) as u8;
     let msb = (next_addr >> 8) as u8;
+    game_state.tick();
     dec_sp(game_state);
-    game_state.write(msb, game_state.get_register16(Register::SP));
+    game_state.cpu_write(msb, game_state.get_register16(Register::SP));
     dec_sp(game_state);
-    game_state.write(lsb, game_state.get_register16(Register::SP));
-    jp_n16(game_state);
+    game_state.cpu_write(lsb, game_state.get_register16(Register::SP));
+    let jump_addr = ((call_msb as u16) << 8) | (call_lsb as u16);
+    game_state.set_register16(Register::PC, jump_addr);
+    game_state.set_pc_moved(true);
     6
 }
 
@@ -855,8 +866,8 @@ pub fn jp_hl(game_state: &mut GameState) -> u8 {
 }
 
 pub fn jp_n16(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let jump_addr = ((msb as u16) << 8) | (lsb as u16);
     game_state.set_register16(Register::PC, jump_addr);
     game_state.set_pc_moved(true);
@@ -877,7 +888,7 @@ pub fn jp_cc(game_state: &mut GameState, cc: CC) -> u8 {
 }
 
 pub fn jr_e8(game_state: &mut GameState) -> u8 {
-    let offset = game_state.read(game_state.get_register16(Register::PC) + 1) as i8;
+    let offset = game_state.cpu_read(game_state.get_register16(Register::PC) + 1) as i8;
     let jump_addr = (game_state.get_register16(Register::PC) as i16 + 2) + offset as i16;
     game_state.set_register16(Register::PC, jump_addr as u16);
     game_state.set_pc_moved(true);
@@ -917,6 +928,8 @@ pub fn ret_cc(game_state: &mut GameState, cc: CC) -> u8 {
         || (matches!(cc, CC::C) && flags.C)
         || (matches!(cc, CC::NC) && !flags.C)
     {
+        // Checking the condition costs an M-cycle before the pops
+        game_state.tick();
         ret(game_state);
         return 5;
     }
@@ -927,10 +940,11 @@ pub fn rst_vec(game_state: &mut GameState, vec: u8) -> u8 {
     let next_addr = game_state.get_register16(Register::PC) + 1;
     let n_lsb = (next_addr & 0x00FF) as u8;
     let n_msb = (next_addr >> 8) as u8;
+    game_state.tick();
     dec_sp(game_state);
-    game_state.write(n_msb, game_state.get_register16(Register::SP));
+    game_state.cpu_write(n_msb, game_state.get_register16(Register::SP));
     dec_sp(game_state);
-    game_state.write(n_lsb, game_state.get_register16(Register::SP));
+    game_state.cpu_write(n_lsb, game_state.get_register16(Register::SP));
     let lsb = vec;
     let msb = 0u8;
     let jump_addr = ((msb as u16) << 8) | (lsb as u16);
@@ -980,7 +994,7 @@ fn add16_special(a: u16, b: u16) -> (u16, bool, bool) {
 }
 
 pub fn add_sp_e8(game_state: &mut GameState) -> u8 {
-    let e = game_state.read(game_state.get_register16(Register::PC) + 1) as i8;
+    let e = game_state.cpu_read(game_state.get_register16(Register::PC) + 1) as i8;
     let (result, half_carry, carry_out) =
         add16_special(game_state.get_register16(Register::SP), e as i16 as u16);
     game_state.set_register16(Register::SP, result);
@@ -1013,7 +1027,7 @@ pub fn inc_sp(game_state: &mut GameState) -> u8 {
 }
 
 pub fn ld_hl_spe8(game_state: &mut GameState) -> u8 {
-    let e = game_state.read(game_state.get_register16(Register::PC) + 1) as i8;
+    let e = game_state.cpu_read(game_state.get_register16(Register::PC) + 1) as i8;
     let (result, half_carry, carry_out) =
         add16_special(game_state.get_register16(Register::SP), e as i16 as u16);
     game_state.set_register16(Register::SP, result);
@@ -1031,20 +1045,20 @@ pub fn ld_hl_spe8(game_state: &mut GameState) -> u8 {
 }
 
 pub fn ld_n16addr_sp(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let addr = ((msb as u16) << 8) | (lsb as u16);
     let sp_val = game_state.get_register16(Register::SP);
     let val1 = (sp_val & 0xFF) as u8;
     let val2 = (sp_val >> 8) as u8;
-    game_state.write(val1, addr);
-    game_state.write(val2, addr + 1);
+    game_state.cpu_write(val1, addr);
+    game_state.cpu_write(val2, addr + 1);
     5
 }
 
 pub fn ld_sp_n16addr(game_state: &mut GameState) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::PC) + 1);
-    let msb = game_state.read(game_state.get_register16(Register::PC) + 2);
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::PC) + 1);
+    let msb = game_state.cpu_read(game_state.get_register16(Register::PC) + 2);
     let val = ((msb as u16) << 8) | (lsb as u16);
     game_state.set_register16(Register::SP, val);
     3
@@ -1056,9 +1070,9 @@ pub fn ld_sp_hl(game_state: &mut GameState) -> u8 {
 }
 
 pub fn pop_r16(game_state: &mut GameState, r: Register) -> u8 {
-    let lsb = game_state.read(game_state.get_register16(Register::SP));
+    let lsb = game_state.cpu_read(game_state.get_register16(Register::SP));
     inc_sp(game_state);
-    let msb = game_state.read(game_state.get_register16(Register::SP));
+    let msb = game_state.cpu_read(game_state.get_register16(Register::SP));
     inc_sp(game_state);
     let val = ((msb as u16) << 8) | (lsb as u16);
     game_state.set_register16(r, val);
@@ -1069,21 +1083,25 @@ pub fn push_r16(game_state: &mut GameState, r: Register) -> u8 {
     let reg_val = game_state.get_register16(r);
     let lsb = (reg_val & 0x00FF) as u8;
     let msb = (reg_val >> 8) as u8;
+    game_state.tick();
     dec_sp(game_state);
-    game_state.write(msb, game_state.get_register16(Register::SP));
+    game_state.cpu_write(msb, game_state.get_register16(Register::SP));
     dec_sp(game_state);
-    game_state.write(lsb, game_state.get_register16(Register::SP));
+    game_state.cpu_write(lsb, game_state.get_register16(Register::SP));
     4
 }
 
 // Interrupts
+// Also cancels an EI that hasn't taken effect yet
 pub fn di(game_state: &mut GameState) -> u8 {
     game_state.set_interrupts(false);
+    game_state.set_ei_pending(false);
     1
 }
 
+// IME is only set once the next instruction has run, see CPU::step
 pub fn ei(game_state: &mut GameState) -> u8 {
-    game_state.set_interrupts(true);
+    game_state.set_ei_pending(true);
     1
 }
 
@@ -1127,49 +1145,149 @@ pub fn daa(game_state: &mut GameState) -> u8 {
     1
 }
 
+// https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
 pub fn stop(game_state: &mut GameState) -> u8 {
-    0
+    let pending = game_state.interrupt_pending();
+    if game_state.joypad_line_low() {
+        // A held button would wake STOP straight away, so it falls back to HALT or does nothing
+        if !pending {
+            game_state.set_halted(true);
+        }
+    } else {
+        game_state.write(0, 0xFF04);
+        game_state.set_stopped(true);
+    }
+
+    // The byte after STOP is skipped unless an interrupt is already pending
+    let length = if pending { 1 } else { 2 };
+    let pc = game_state.get_register16(Register::PC);
+    game_state.set_register16(Register::PC, pc.wrapping_add(length));
+    game_state.set_pc_moved(true);
+    1
 }
 
-// TODO HALT Implementation
+// https://gbdev.io/pandocs/halt.html
 pub fn halt(game_state: &mut GameState) -> u8 {
-    0
-}
-
-pub fn interrupt_handler(game_state: &mut GameState) -> bool {
-    let i_flag = game_state.read(0xFF0F);
-    let i_enable = game_state.get_i_enable();
-    if (i_flag << 3) != 0 {
-        game_state.set_interrupts(false);
-        let jump_addr;
-        if i_flag & INT_VBLANK != 0 && i_enable & INT_VBLANK != 0 {
-            game_state.write(i_flag & !INT_VBLANK, 0xFF0F);
-            jump_addr = 0x0040;
-        } else if i_flag & INT_LCD != 0 && i_enable & INT_LCD != 0 {
-            game_state.write(i_flag & !INT_LCD, 0xFF0F);
-            jump_addr = 0x0048;
-        } else if i_flag & INT_TIMER != 0 && i_enable & INT_TIMER != 0 {
-            game_state.write(i_flag & !INT_TIMER, 0xFF0F);
-            jump_addr = 0x0050;
-        // println!("EXECUTING TIMER INTERRUPT");
-        } else if i_flag & INT_SERIAL != 0 && i_enable & INT_SERIAL != 0 {
-            game_state.write(i_flag & !INT_SERIAL, 0xFF0F);
-            jump_addr = 0x0058;
-        } else {
-            game_state.write(i_flag & !INT_JOYPAD, 0xFF0F);
-            jump_addr = 0x0060;
+    if !game_state.get_interrupts() && game_state.interrupt_pending() {
+        // HALT bug: with IME off and an interrupt already pending the CPU doesn't halt, and fails
+        // to increment PC when fetching the next opcode
+        game_state.set_halt_bug(true);
+    } else {
+        // Wakes once IE & IF is non-zero, servicing the interrupt only if IME is set
+        game_state.set_halted(true);
+    }
+    1
+}
+
+// https://gbdev.io/pandocs/Interrupts.html#interrupt-handling
+pub fn interrupt_handler(game_state: &mut GameState) -> u8 {
+    // An EI just before the dispatch mustn't re-enable interrupts ahead of the handler
+    game_state.set_interrupts(false);
+    game_state.set_ei_pending(false);
+    game_state.tick();
+    game_state.tick();
+
+    let curr_addr = game_state.get_register16(Register::PC);
+    let lsb = (curr_addr & 0x00FF) as u8;
+    let msb = (curr_addr >> 8) as u8;
+    dec_sp(game_state);
+    game_state.cpu_write(msb, game_state.get_register16(Register::SP));
+
+    // The vector is only picked after the high byte is pushed, so a push that lands on IE can
+    // cancel the dispatch, in which case PC ends up at 0x0000
+    let pending = game_state.get_i_flag() & game_state.get_i_enable();
+    let jump_addr = match INTERRUPT_VECTORS.iter().find(|(int, _)| pending & int != 0) {
+        Some(&(int, vector)) => {
+            game_state.write(game_state.get_i_flag() & !int, 0xFF0F);
+            vector
         }
+        None => 0x0000,
+    };
 
-        let curr_addr = game_state.get_register16(Register::PC);
-        let lsb = (curr_addr & 0x00FF) as u8;
-        let msb = (curr_addr >> 8) as u8;
-        dec_sp(game_state);
-        game_state.write(msb, game_state.get_register16(Register::SP));
-        dec_sp(game_state);
-        game_state.write(lsb, game_state.get_register16(Register::SP));
-        game_state.set_register16(Register::PC, jump_addr);
-        game_state.set_pc_moved(true);
-        return true;
+    dec_sp(game_state);
+    game_state.cpu_write(lsb, game_state.get_register16(Register::SP));
+    game_state.set_register16(Register::PC, jump_addr);
+    5
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cartridge::CartridgeHeader;
+    use crate::constants::INT_VBLANK;
+    use crate::cpu::CPU;
+    use crate::state::{GameState, Register};
+
+    fn run(program: &[u8], a: u8, hl_value: u8) -> (GameState, Vec<u8>) {
+        let rom = CartridgeHeader::test_rom(0x00, 0x00, program);
+        let mut game_state = GameState::from_rom(rom).unwrap();
+        game_state.set_register8(Register::A, a);
+        game_state.write(hl_value, 0xC000);
+        let cpu = CPU::initialize();
+        let cycles = (0..2).map(|_| cpu.step(&mut game_state)).collect();
+        (game_state, cycles)
+    }
+
+    #[test]
+    fn cb_prefix_at_0xffff_wraps_around_to_0x0000() {
+        let mut game_state = GameState::from_rom(CartridgeHeader::test_rom(0, 0, &[])).unwrap();
+        // IE holds the prefix and the ROM's 0x00 at 0x0000 makes it RLC B
+        game_state.write(0xCB, 0xFFFF);
+        game_state.set_register16(Register::PC, 0xFFFF);
+        game_state.set_register8(Register::B, 0x81);
+        CPU::initialize().step(&mut game_state);
+        assert_eq!(game_state.get_register8(Register::B), 0x03);
+        assert_eq!(game_state.get_register16(Register::PC), 0x0001);
+    }
+
+    #[test]
+    fn di_cancels_a_pending_ei() {
+        // EI, DI, NOP with the VBlank interrupt waiting the whole time
+        let mut game_state =
+            GameState::from_rom(CartridgeHeader::test_rom(0, 0, &[0xFB, 0xF3, 0x00])).unwrap();
+        game_state.write(INT_VBLANK, 0xFFFF);
+        game_state.write(INT_VBLANK, 0xFF0F);
+        let cpu = CPU::initialize();
+        for _ in 0..3 {
+            cpu.step(&mut game_state);
+        }
+        assert!(!game_state.get_interrupts());
+        assert_eq!(game_state.get_register16(Register::PC), 0x0103);
+    }
+
+    #[test]
+    fn dispatch_cancels_a_pending_ei() {
+        // EI with IME already set, then the VBlank interrupt arrives
+        let mut game_state = GameState::from_rom(CartridgeHeader::test_rom(0, 0, &[0xFB])).unwrap();
+        game_state.set_interrupts(true);
+        game_state.write(INT_VBLANK, 0xFFFF);
+        game_state.write(0, 0xFF0F);
+        let cpu = CPU::initialize();
+        cpu.step(&mut game_state);
+        game_state.write(INT_VBLANK, 0xFF0F);
+        cpu.step(&mut game_state);
+        assert_eq!(game_state.get_register16(Register::PC), 0x0040);
+
+        // The handler's first instruction runs with interrupts still off
+        cpu.step(&mut game_state);
+        assert_eq!(game_state.get_register16(Register::PC), 0x0041);
+        assert!(!game_state.get_interrupts());
+    }
+
+    #[test]
+    fn ld_r16_n16_takes_three_m_cycles() {
+        let (game_state, cycles) = run(&[0x21, 0x00, 0xC0, 0x00], 0, 0);
+        assert_eq!(game_state.get_register16(Register::HL), 0xC000);
+        assert_eq!(cycles[0], 12);
+    }
+
+    #[test]
+    fn logic_ops_on_hladdr_update_a_and_leave_memory_alone() {
+        // LD HL,$C000 followed by AND/XOR/OR (HL)
+        for (opcode, expected) in [(0xA6, 0b1000), (0xAE, 0b0110), (0xB6, 0b1110)] {
+            let (game_state, cycles) = run(&[0x21, 0x00, 0xC0, opcode], 0b1100, 0b1010);
+            assert_eq!(game_state.get_register8(Register::A), expected);
+            assert_eq!(game_state.read(0xC000), 0b1010);
+            assert_eq!(cycles[1], 8);
+        }
     }
-    false
 }
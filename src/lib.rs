@@ -0,0 +1,22 @@
+// The emulator core, free of any windowing or audio dependency. Frontends drive it through
+// Emulator and present its framebuffer and samples however they like
+mod apu;
+mod boot;
+mod cartridge;
+mod constants;
+mod cpu;
+mod emulator;
+mod instructions;
+mod opcodes;
+mod ppu;
+mod rtc;
+mod savestate;
+mod serial;
+mod state;
+
+pub use boot::Model;
+pub use cartridge::{CartridgeHeader, CgbSupport, ImageSource, StaticImage};
+pub use emulator::{Buttons, CpuRegisters, Emulator, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use opcodes::{OpcodeInfo, Operand, CB_OPCODES, OPCODES};
+pub use rtc::{SystemClock, TimeSource};
+pub use serial::{Loopback, SerialLink, SocketLink, StdoutLink};
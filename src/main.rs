@@ -1,27 +1,169 @@
+use std::env;
 use std::error::Error;
-mod constants;
-mod cpu;
-mod instructions;
-mod logger;
-mod ppu;
-mod state;
-use cpu::CPU;
-use minifb::{Key, Scale, Window, WindowOptions};
-use ppu::PPU;
-use state::GameState;
-
-fn main() -> Result<(), Box<dyn Error>> {
-    // let cart = state::Cartridge::load_rom("roms/tetris.gb")?;
-    let mut game_state = GameState::start_game("/home/aarohg/Projects/my-emulator/roms/tetris.gb")?;
-    let cpu = CPU::initialize();
-    let mut ppu = PPU::initialize();
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Instant;
+#[cfg(feature = "audio")]
+mod audio;
+mod cli;
+use cli::{Command, Link, Options};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use my_emulator::{
+    Buttons, CgbSupport, Emulator, Loopback, SerialLink, SocketLink, StdoutLink, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+
+const STATE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+
+// Slot files sit next to the ROM or in --save-dir, e.g. tetris.ss1
+fn state_slot_path(options: &Options, slot: usize) -> PathBuf {
+    let rom_path = Path::new(&options.rom_path);
+    let path = match &options.save_dir {
+        Some(save_dir) => save_dir.join(rom_path.file_name().unwrap_or_default()),
+        None => rom_path.to_path_buf(),
+    };
+    path.with_extension(format!("ss{}", slot + 1))
+}
+
+fn load_state_slot(path: &Path, emulator: &mut Emulator) -> io::Result<()> {
+    emulator.load_state(&fs::read(path)?)
+}
+
+// The DMG draws one frame every 70224 T-cycles
+const GB_FRAMES_PER_SECOND: f64 = 4194304.0 / 70224.0;
+
+// Runs flat out without a window to time the emulation loop on its own
+fn run_headless(emulator: &mut Emulator, frames: u32) {
+    let start = Instant::now();
+    for _ in 0..frames {
+        emulator.run_frame();
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let fps = frames as f64 / elapsed;
+    println!(
+        "{frames} frames in {elapsed:.3}s, {fps:.0} fps ({:.1}x real time)",
+        fps / GB_FRAMES_PER_SECOND
+    );
+}
+
+fn open_rom(options: &Options) -> Result<Emulator, String> {
+    let path = &options.rom_path;
+    let emulator = match &options.save_dir {
+        Some(save_dir) => fs::create_dir_all(save_dir)
+            .map_err(|e| format!("cannot create save directory {}: {e}", save_dir.display()))
+            .and_then(|()| {
+                Emulator::open_with_save_dir(path, save_dir).map_err(|e| rom_error(path, e))
+            })?,
+        None => Emulator::open(path).map_err(|e| rom_error(path, e))?,
+    };
+    Ok(emulator)
+}
+
+fn rom_error(path: &str, e: io::Error) -> String {
+    match e.kind() {
+        ErrorKind::NotFound => format!("ROM file {path} does not exist"),
+        ErrorKind::InvalidData => format!("{path} is not a valid Game Boy ROM: {e}"),
+        _ => format!("cannot read {path}: {e}"),
+    }
+}
+
+fn open_link(link: &Link) -> io::Result<Box<dyn SerialLink>> {
+    Ok(match link {
+        Link::Stdout => Box::new(StdoutLink),
+        Link::Loopback => Box::new(Loopback),
+        Link::TcpListen(addr) => {
+            println!("Waiting for the other Game Boy to connect to {addr}");
+            Box::new(SocketLink::listen_tcp(addr.as_str())?)
+        }
+        Link::Tcp(addr) => Box::new(SocketLink::connect_tcp(addr.as_str())?),
+        #[cfg(unix)]
+        Link::UnixListen(path) => {
+            println!(
+                "Waiting for the other Game Boy to connect to {}",
+                path.display()
+            );
+            Box::new(SocketLink::listen_unix(path)?)
+        }
+        #[cfg(unix)]
+        Link::Unix(path) => Box::new(SocketLink::connect_unix(path)?),
+        #[cfg(not(unix))]
+        Link::UnixListen(_) | Link::Unix(_) => {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "Unix sockets aren't available on this platform",
+            ));
+        }
+    })
+}
+
+fn main() {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\nRun with --help to see the options");
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut emulator = open_rom(options)?;
+    let header = emulator.cartridge_header();
+    if header.cgb == CgbSupport::CgbOnly {
+        eprintln!("Warning: {} requires a Game Boy Color", header.title);
+    }
+    let title = format!("{} - ESC to exit", header.title);
+    match &options.boot_rom {
+        Some(path) => fs::read(path)
+            .and_then(|boot_rom| emulator.boot(boot_rom))
+            .map_err(|e| format!("cannot load boot ROM {}: {e}", path.display()))?,
+        None => emulator.restart(options.model),
+    }
+    if let Some(link) = &options.link {
+        let link = open_link(link).map_err(|e| format!("cannot open the link cable: {e}"))?;
+        emulator.set_serial_link(link);
+    }
+    emulator.set_palette(options.palette);
+    emulator.set_trace(options.trace);
+
+    if options.headless {
+        run_headless(&mut emulator, options.frames.unwrap_or_default());
+        emulator.flush_save()?;
+        return Ok(());
+    }
+
+    #[cfg(feature = "audio")]
+    let audio = if options.mute {
+        None
+    } else {
+        match audio::AudioOutput::open() {
+            Ok(audio) => {
+                emulator.set_audio_sample_rate(audio.sample_rate());
+                Some(audio)
+            }
+            Err(e) => {
+                eprintln!("Audio disabled: {e}");
+                None
+            }
+        }
+    };
 
     let mut window = Window::new(
-        "Test - ESC to exit",
-        160,
-        144,
+        &title,
+        SCREEN_WIDTH,
+        SCREEN_HEIGHT,
         WindowOptions {
-            scale: Scale::X2,
+            scale: options.scale,
             ..Default::default()
         },
     )
@@ -29,58 +171,87 @@ fn main() -> Result<(), Box<dyn Error>> {
         panic!("{}", e);
     });
 
-    // Limit to max ~60 fps update rate
-    window.set_target_fps(60);
+    window.set_target_fps(options.fps);
 
-    // for i in 0..5000 {
-    // 	println!("OP 0x{:04X}: 0x{:02X}", 0x0100 + i, game_state.read(0x0100 + i as u16));
-    // }
+    // Battery saves are flushed every few seconds so a crash loses little progress
+    const FRAMES_PER_SAVE_FLUSH: u32 = 300;
+    let mut frames_since_flush = 0;
+    let mut frames = 0;
 
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        let (mut a, mut b, mut start, mut select, mut down, mut up, mut left, mut right) =
-            (false, false, false, false, false, false, false, false);
+    while window.is_open()
+        && !window.is_key_down(Key::Escape)
+        && options.frames.is_none_or(|limit| frames < limit)
+    {
+        emulator.set_buttons(Buttons {
+            // Arrow Keys
+            down: window.is_key_down(Key::Down),
+            up: window.is_key_down(Key::Up),
+            left: window.is_key_down(Key::Left),
+            right: window.is_key_down(Key::Right),
+            // Start, Select, B, A
+            select: window.is_key_down(Key::S),
+            start: window.is_key_down(Key::A),
+            b: window.is_key_down(Key::X),
+            a: window.is_key_down(Key::Z),
+        });
 
-        // Arrow Keys
-        if window.is_key_down(Key::Down) {
-            down = true;
+        // IJKL tilt the cart for MBC7 accelerometer games
+        let mut tilt_x = 0.0;
+        let mut tilt_y = 0.0;
+        if window.is_key_down(Key::J) {
+            tilt_x -= 1.0;
         }
-        if window.is_key_down(Key::Up) {
-            up = true;
+        if window.is_key_down(Key::L) {
+            tilt_x += 1.0;
         }
-        if window.is_key_down(Key::Left) {
-            left = true;
+        if window.is_key_down(Key::I) {
+            tilt_y -= 1.0;
         }
-        if window.is_key_down(Key::Right) {
-            right = true;
+        if window.is_key_down(Key::K) {
+            tilt_y += 1.0;
         }
+        emulator.set_tilt(tilt_x, tilt_y);
 
-        // Start, Select, B, A
-        if window.is_key_down(Key::S) {
-            select = true;
-        }
-        if window.is_key_down(Key::A) {
-            start = true;
-        }
-        if window.is_key_down(Key::X) {
-            b = true;
+        // F1-F4 save to a slot, holding shift loads from it instead
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        for (slot, key) in STATE_SLOT_KEYS.into_iter().enumerate() {
+            if !window.is_key_pressed(key, KeyRepeat::No) {
+                continue;
+            }
+            let path = state_slot_path(options, slot);
+            let result = if shift {
+                load_state_slot(&path, &mut emulator)
+            } else {
+                fs::write(&path, emulator.save_state())
+            };
+            match result {
+                Ok(()) if shift => println!("Loaded state from {}", path.display()),
+                Ok(()) => println!("Saved state to {}", path.display()),
+                Err(e) => eprintln!("Save state slot {} failed: {e}", slot + 1),
+            }
         }
 
-        if window.is_key_down(Key::Z) {
-            a = true;
-        }
+        emulator.run_frame();
+        frames += 1;
+        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        window
+            .update_with_buffer(emulator.framebuffer(), SCREEN_WIDTH, SCREEN_HEIGHT)
+            .unwrap();
 
-        game_state.update_joypad(a, b, start, select, up, down, left, right);
+        #[cfg(feature = "audio")]
+        if let Some(audio) = &audio {
+            audio.queue(&emulator.audio_samples());
+        }
 
-        let cycles = cpu.step(&mut game_state);
-        let update = ppu.step(2 * cycles, &mut game_state);
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        if update {
-            // game_state.print_oam();
-            window
-                .update_with_buffer(&ppu.current_fb, 160, 144)
-                .unwrap();
+        frames_since_flush += 1;
+        if frames_since_flush == FRAMES_PER_SAVE_FLUSH {
+            frames_since_flush = 0;
+            if let Err(e) = emulator.flush_save() {
+                eprintln!("Failed to write save file: {e}");
+            }
         }
     }
 
+    emulator.flush_save()?;
     Ok(())
 }
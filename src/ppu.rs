@@ -1,8 +1,11 @@
 // Reference Manual - https://gbdev.io/pandocs/Graphics.html
+// Pixel FIFO - https://gbdev.io/pandocs/pixel_fifo.html
 use crate::constants::*;
-use crate::state::GameState;
+use crate::savestate::{invalid_state, StateReader, StateWriter};
+use crate::state::Gameboy;
+use std::io::Result;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 struct OamEntry {
     y_pos: u8,
     x_pos: u8,
@@ -10,230 +13,612 @@ struct OamEntry {
     attrs: u8,
 }
 
-fn gb_color_to_u32(code: u8) -> u32 {
-    match code {
-        0b00 => 0xFFFFFFFF, // white
-        0b01 => 0xFF9BB7FF, // light blue
-        0b10 => 0xFF4863A0, // medium blue
-        0b11 => 0xFF0A0A40, // dark navy
-        // 0b00 => 0xFFFFFFFF,
-        // 0b01 => 0xFFd186a8,
-        // 0b10 => 0xFF64c41a,
-        // 0b11 => 0xFF294f4e,
-        _ => 0xFFFF00FF,
+// A sprite pixel waiting to be mixed with the background. Colour 0 is transparent
+#[derive(Clone, Copy, Default)]
+struct ObjPixel {
+    color: u8,
+    behind_bg: bool,
+    obp1: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    HBlank,
+    VBlank,
+    OamScan,
+    Drawing,
+}
+
+impl Mode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(Mode::HBlank),
+            1 => Some(Mode::VBlank),
+            2 => Some(Mode::OamScan),
+            3 => Some(Mode::Drawing),
+            _ => None,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Mode::HBlank => 0,
+            Mode::VBlank => 1,
+            Mode::OamScan => 2,
+            Mode::Drawing => 3,
+        }
     }
 }
 
-fn get_tile_pixel(
-    lcdc: u8,
-    tile_index: u8,
-    x_tile: u8,
-    y_tile: u8,
-    game_state: &mut GameState,
-    sprite: bool,
-) -> u8 {
-    let tile;
-    let tile_addr;
+// The shades colour IDs 0-3 are drawn with, lightest first
+const DEFAULT_SHADES: [u32; 4] = [
+    0xFFFFFFFF, // white
+    0xFF9BB7FF, // light blue
+    0xFF4863A0, // medium blue
+    0xFF0A0A40, // dark navy
+];
+
+const OAM_SCAN_DOTS: u16 = 80;
+const MAX_LINE_SPRITES: usize = 10;
+// The fetcher spends two dots on each of the tile number, the low and the high data byte,
+// then tries every dot to push its row into the background FIFO
+const FETCH_PUSH: u8 = 6;
+const SPRITE_FETCH_DOTS: u8 = 6;
+
+fn tile_data_addr(lcdc: u8, tile_index: u8, sprite: bool) -> u16 {
     if lcdc & LCDC_TILE_BG_DATA == 0 && !sprite {
-        // 0x9000 addressing mode
+        // 0x8800 addressing mode, where the index is signed and based at 0x9000
         if tile_index <= 127 {
-            tile_addr = 0x8000 + (tile_index as u16 * 16);
+            0x9000 + (tile_index as u16 * 16)
         } else {
-            tile_addr = 0x8800 + ((tile_index - 128) as u16 * 16);
+            0x8800 + ((tile_index - 128) as u16 * 16)
         }
     } else {
         // 0x8000 addressing mode
-        tile_addr = 0x8000 + (tile_index as u16 * 16);
-    }
-    tile = game_state.get_tile_from_addr(tile_addr);
-    let tile_row = tile[y_tile as usize];
-    let upper_byte = (tile_row & 0xFF00) >> 8;
-    let lower_byte = tile_row & 0x00FF;
-    let upper_bit = (upper_byte >> (7 - x_tile)) & 1;
-    let lower_bit = (lower_byte >> (7 - x_tile)) & 1;
-    ((upper_bit << 1) | lower_bit) as u8
+        0x8000 + (tile_index as u16 * 16)
+    }
+}
+
+fn row_color(low: u8, high: u8, x: u8) -> u8 {
+    (((high >> (7 - x)) & 1) << 1) | ((low >> (7 - x)) & 1)
+}
+
+// Palettes hold the shade for each colour ID in two bits, ID 0 lowest
+fn palette_shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
 }
 
 pub struct PPU {
-    dot_counter: u128,
-    active_sprites: [Option<OamEntry>; 10],
+    mode: Mode,
+    line_dot: u16,
+    // Sprites picked by the OAM scan for this line, and which of them have been fetched
+    line_sprites: [OamEntry; MAX_LINE_SPRITES],
+    sprite_count: u8,
+    sprites_fetched: u16,
+    // The background FIFO only takes a new row once it's empty, so it never holds more than one
+    bg_fifo: [u8; 8],
+    bg_len: u8,
+    // A ring lined up with the background FIFO: obj_head is the pixel that goes out next
+    obj_fifo: [ObjPixel; 8],
+    obj_head: u8,
+    fetcher_dot: u8,
+    fetcher_x: u8,
+    tile_index: u8,
+    tile_low: u8,
+    tile_high: u8,
+    // The first fetch of a line is thrown away, which is part of why mode 3 takes 172 dots
+    first_fetch: bool,
+    in_window: bool,
+    // The window keeps its own line counter, which only moves on lines it was drawn on. It
+    // can only show up once LY has matched WY at the start of a line this frame
+    window_line: u8,
+    wy_triggered: bool,
+    // Triggering the window at WX=166 carries it over onto the whole next line
+    window_wrap: bool,
+    // Dots left on the sprite being fetched, which stalls the background meanwhile
+    sprite_fetch_dots: u8,
+    sprite_fetch_index: u8,
+    // SCX's low bits: pixels dropped off the start of the line to scroll it finely
+    discard: u8,
+    lx: u8,
+    // The first line after the LCD is switched on has no OAM scan, and the frame it starts
+    // never reaches the screen
+    lcd_starting: bool,
+    blank_frame: bool,
     pub current_fb: Vec<u32>,
+    shades: [u32; 4],
 }
 
 impl PPU {
     pub fn initialize() -> Self {
-        const NONE: Option<OamEntry> = None;
         Self {
-            dot_counter: 0,
-            active_sprites: [NONE; 10],
-            current_fb: vec![0; 144 * 160],
+            mode: Mode::OamScan,
+            line_dot: 0,
+            line_sprites: [OamEntry::default(); MAX_LINE_SPRITES],
+            sprite_count: 0,
+            sprites_fetched: 0,
+            bg_fifo: [0; 8],
+            bg_len: 0,
+            obj_fifo: [ObjPixel::default(); 8],
+            obj_head: 0,
+            fetcher_dot: 0,
+            fetcher_x: 0,
+            tile_index: 0,
+            tile_low: 0,
+            tile_high: 0,
+            first_fetch: true,
+            in_window: false,
+            window_line: 0,
+            wy_triggered: false,
+            window_wrap: false,
+            sprite_fetch_dots: 0,
+            sprite_fetch_index: 0,
+            discard: 0,
+            lx: 0,
+            lcd_starting: false,
+            blank_frame: false,
+            current_fb: vec![DEFAULT_SHADES[0]; 144 * 160],
+            shades: DEFAULT_SHADES,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            current_fb: vec![self.shades[0]; 144 * 160],
+            shades: self.shades,
+            ..Self::initialize()
+        };
+    }
+
+    // LY is held at 0 and STAT reports HBlank until the LCD is switched back on, and the
+    // screen goes blank
+    pub fn switch_off(&mut self, gb: &mut Gameboy) {
+        *self = Self {
+            current_fb: std::mem::take(&mut self.current_fb),
+            shades: self.shades,
+            ..Self::initialize()
+        };
+        self.current_fb.fill(self.shades[0]);
+        self.set_mode(Mode::HBlank, gb);
+        gb.set_ly(0);
+    }
+
+    pub fn switch_on(&mut self) {
+        self.mode = Mode::HBlank;
+        self.line_dot = 0;
+        self.lcd_starting = true;
+        self.blank_frame = true;
+    }
+
+    // Display colours aren't machine state, so they stay out of save states
+    pub fn set_shades(&mut self, shades: [u32; 4]) {
+        self.shades = shades;
+    }
+
+    // Takes on the state `loaded` was given by load_state, keeping what's on screen
+    pub fn restore(&mut self, loaded: Self) {
+        *self = Self {
+            current_fb: std::mem::take(&mut self.current_fb),
+            shades: self.shades,
+            ..loaded
+        };
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.mode.bits());
+        w.u16(self.line_dot);
+        for sprite in &self.line_sprites {
+            w.bytes(&[sprite.y_pos, sprite.x_pos, sprite.tile_index, sprite.attrs]);
+        }
+        w.u8(self.sprite_count);
+        w.u16(self.sprites_fetched);
+        w.bytes(&self.bg_fifo);
+        w.u8(self.bg_len);
+        for pixel in &self.obj_fifo {
+            w.u8(pixel.color);
+            w.bool(pixel.behind_bg);
+            w.bool(pixel.obp1);
         }
+        w.u8(self.obj_head);
+        w.bytes(&[
+            self.fetcher_dot,
+            self.fetcher_x,
+            self.tile_index,
+            self.tile_low,
+            self.tile_high,
+        ]);
+        w.bool(self.first_fetch);
+        w.bool(self.in_window);
+        w.u8(self.window_line);
+        w.bool(self.wy_triggered);
+        w.bool(self.window_wrap);
+        w.u8(self.sprite_fetch_dots);
+        w.u8(self.sprite_fetch_index);
+        w.u8(self.discard);
+        w.u8(self.lx);
+        w.bool(self.lcd_starting);
+        w.bool(self.blank_frame);
     }
 
-    fn reset_active_entries(&mut self) {
-        const NONE: Option<OamEntry> = None;
-        self.active_sprites = [NONE; 10];
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        let mode = r.u8()?;
+        let Some(mode) = Mode::from_bits(mode) else {
+            return Err(invalid_state(format!("invalid PPU mode {mode}")));
+        };
+        let line_dot = r.u16()?;
+        let mut line_sprites = [OamEntry::default(); MAX_LINE_SPRITES];
+        for sprite in &mut line_sprites {
+            let entry = r.bytes(4)?;
+            *sprite = OamEntry {
+                y_pos: entry[0],
+                x_pos: entry[1],
+                tile_index: entry[2],
+                attrs: entry[3],
+            };
+        }
+        let sprite_count = r.u8()?;
+        let sprites_fetched = r.u16()?;
+        let mut bg_fifo = [0; 8];
+        r.fill(&mut bg_fifo)?;
+        let bg_len = r.u8()?;
+        let mut obj_fifo = [ObjPixel::default(); 8];
+        for pixel in &mut obj_fifo {
+            pixel.color = r.u8()?;
+            pixel.behind_bg = r.bool()?;
+            pixel.obp1 = r.bool()?;
+        }
+        let obj_head = r.u8()?;
+        let fetcher = r.bytes(5)?;
+        let first_fetch = r.bool()?;
+        let in_window = r.bool()?;
+        let window_line = r.u8()?;
+        let wy_triggered = r.bool()?;
+        let window_wrap = r.bool()?;
+        let sprite_fetch_dots = r.u8()?;
+        let sprite_fetch_index = r.u8()?;
+        let discard = r.u8()?;
+        let lx = r.u8()?;
+        let lcd_starting = r.bool()?;
+        let blank_frame = r.bool()?;
+        if line_dot >= DOTS_PER_SL
+            || sprite_count as usize > MAX_LINE_SPRITES
+            || sprite_fetch_index as usize >= MAX_LINE_SPRITES
+            || bg_len > 8
+            || obj_head >= 8
+            || lx > 160
+        {
+            return Err(invalid_state("PPU state out of range".to_string()));
+        }
+
+        *self = Self {
+            mode,
+            line_dot,
+            line_sprites,
+            sprite_count,
+            sprites_fetched,
+            bg_fifo,
+            bg_len,
+            obj_fifo,
+            obj_head,
+            fetcher_dot: fetcher[0],
+            fetcher_x: fetcher[1],
+            tile_index: fetcher[2],
+            tile_low: fetcher[3],
+            tile_high: fetcher[4],
+            first_fetch,
+            in_window,
+            window_line,
+            wy_triggered,
+            window_wrap,
+            sprite_fetch_dots,
+            sprite_fetch_index,
+            discard,
+            lx,
+            lcd_starting,
+            blank_frame,
+            current_fb: std::mem::take(&mut self.current_fb),
+            shades: self.shades,
+        };
+        Ok(())
     }
 
-    fn oam_scan(&mut self, game_state: &mut GameState) {
-        let mut count = 0;
-        let sprite_height = if game_state.get_lcdc() & LCDC_TILE_SIZE == 0 {
-            7
+    // One OAM entry is checked every two dots
+    fn scan_entry(&mut self, entry: u8, gb: &mut Gameboy) {
+        if self.sprite_count as usize == MAX_LINE_SPRITES {
+            return;
+        }
+        let sprite_height = if gb.get_lcdc() & LCDC_TILE_SIZE == 0 {
+            8
         } else {
-            15
+            16
         };
+        let obj_entry = gb.get_oam_entry(entry * 4);
+        let line = gb.get_ly() as u16 + 16;
+        let y_pos = obj_entry[0] as u16;
+        if y_pos <= line && line < y_pos + sprite_height {
+            self.line_sprites[self.sprite_count as usize] = OamEntry {
+                y_pos: obj_entry[0],
+                x_pos: obj_entry[1],
+                tile_index: obj_entry[2],
+                attrs: obj_entry[3],
+            };
+            self.sprite_count += 1;
+        }
+    }
 
-        for i in 0..40 {
-            if count == 10 {
-                break;
-            }
+    fn set_mode(&mut self, mode: Mode, gb: &mut Gameboy) {
+        self.mode = mode;
+        gb.set_stat_mode(mode.bits());
+    }
 
-            let sprite_loc = (i * 4) as u8;
-            let obj_entry = game_state.get_oam_entry(sprite_loc);
-            if (obj_entry[0] <= 8 && obj_entry[0] + sprite_height < 16) || (obj_entry[0] >= 160) {
-                continue;
-            }
+    fn start_drawing(&mut self, gb: &mut Gameboy) {
+        self.set_mode(Mode::Drawing, gb);
+        self.bg_len = 0;
+        self.obj_fifo = [ObjPixel::default(); 8];
+        self.obj_head = 0;
+        self.fetcher_dot = 0;
+        self.fetcher_x = 0;
+        self.first_fetch = true;
+        self.in_window = false;
+        if gb.get_ly() == gb.get_wy() {
+            self.wy_triggered = true;
+        }
+        self.sprites_fetched = 0;
+        self.sprite_fetch_dots = 0;
+        self.discard = gb.get_scx() % 8;
+        self.lx = 0;
+    }
 
-            let y_min = obj_entry[0] - 16;
-            let y_max = y_min + sprite_height;
-            let ly = game_state.get_ly();
-
-            if y_min <= ly && ly <= y_max {
-                self.active_sprites[count] = Some(OamEntry {
-                    y_pos: obj_entry[0],
-                    x_pos: obj_entry[1],
-                    tile_index: obj_entry[2],
-                    attrs: obj_entry[3],
-                });
-                count += 1;
+    // The background/window fetcher, which rereads the scroll registers for every tile
+    fn fetcher_step(&mut self, gb: &mut Gameboy) {
+        let lcdc = gb.get_lcdc();
+        let ly = gb.get_ly();
+        let row = if self.in_window {
+            self.window_line
+        } else {
+            ly.wrapping_add(gb.get_scy())
+        };
+
+        match self.fetcher_dot {
+            1 => {
+                let tile_x = if self.in_window {
+                    self.fetcher_x & 31
+                } else {
+                    (gb.get_scx() / 8).wrapping_add(self.fetcher_x) & 31
+                };
+                let map_bit = if self.in_window {
+                    LCDC_WIN_TILE_MAP
+                } else {
+                    LCDC_TILE_MAP
+                };
+                let i_in_tmap = (row / 8) as u16 * 32 + tile_x as u16;
+                self.tile_index = gb.get_tile_index(map_bit, i_in_tmap);
+            }
+            3 => {
+                let addr = tile_data_addr(lcdc, self.tile_index, false) + (row % 8) as u16 * 2;
+                self.tile_low = gb.get_vram(addr);
             }
+            5 => {
+                let addr = tile_data_addr(lcdc, self.tile_index, false) + (row % 8) as u16 * 2;
+                self.tile_high = gb.get_vram(addr + 1);
+            }
+            FETCH_PUSH => {
+                if self.first_fetch {
+                    // The real fetch starts on this same dot
+                    self.first_fetch = false;
+                    self.fetcher_dot = 1;
+                } else if self.bg_len == 0 {
+                    for x in 0..8 {
+                        self.bg_fifo[x as usize] = row_color(self.tile_low, self.tile_high, x);
+                    }
+                    self.bg_len = 8;
+                    self.fetcher_x = self.fetcher_x.wrapping_add(1);
+                    self.fetcher_dot = 0;
+                }
+                return;
+            }
+            _ => (),
+        }
+        self.fetcher_dot += 1;
+    }
+
+    // The first sprite on this line still to be fetched that starts at or before the next pixel.
+    // With sprites switched off none are fetched, so they don't slow the line down either
+    fn sprite_at_lx(&self, gb: &Gameboy) -> Option<u8> {
+        if gb.get_lcdc() & LCDC_OBJ_ON == 0 {
+            return None;
         }
+        (0..self.sprite_count).find(|&i| {
+            self.sprites_fetched & (1 << i) == 0
+                && self.line_sprites[i as usize].x_pos as u16 <= self.lx as u16 + 8
+        })
     }
 
-    fn gen_scanline(&self, game_state: &mut GameState) -> [u8; 160] {
-        let mut result: [u8; 160] = [0; 160];
-        let ly = game_state.get_ly();
-        let scx = game_state.get_scx();
-        let scy = game_state.get_scy();
-        let wx = game_state.get_wx();
-        let wy = game_state.get_wy();
-        let lcdc = game_state.get_lcdc();
-
-        for x_screen in 0..160u8 {
-            let mut final_pix;
-
-            if lcdc & LCDC_WIN_ON != 0 && (ly >= wy && x_screen >= wx - 7) {
-                // Window enabled
-                let win_x = x_screen - wx + 7;
-                let win_y = ly - wy;
-                let t_x = win_x / 8;
-                let t_y = win_y / 8;
-                let x_tile = (win_x % 8) as u8;
-                let y_tile = (win_y % 8) as u8;
-                let i_in_tmap = (t_y * 32) as u16 + t_x as u16;
-                let tile_index = game_state.get_tile_index(i_in_tmap);
-                final_pix = get_tile_pixel(lcdc, tile_index, x_tile, y_tile, game_state, false);
+    fn fetch_sprite(&mut self, gb: &mut Gameboy) {
+        let index = self.sprite_fetch_index;
+        self.sprites_fetched |= 1 << index;
+        let sprite = self.line_sprites[index as usize];
+        let sprite_height = if gb.get_lcdc() & LCDC_TILE_SIZE == 0 {
+            8
+        } else {
+            16
+        };
+
+        // Masked in case the sprite size changed since the OAM scan
+        let mut row = (gb.get_ly() as u16 + 16 - sprite.y_pos as u16) as u8 & (sprite_height - 1);
+        if sprite.attrs & SPRITE_Y_FLIP != 0 {
+            row = sprite_height - 1 - row;
+        }
+        let tile_index = if sprite_height == 16 {
+            (sprite.tile_index & 0b1111_1110) + row / 8
+        } else {
+            sprite.tile_index
+        };
+        let addr = tile_data_addr(gb.get_lcdc(), tile_index, true) + (row % 8) as u16 * 2;
+        let low = gb.get_vram(addr);
+        let high = gb.get_vram(addr + 1);
+
+        // Sprites hanging off the left edge lose the pixels that are already past
+        let skip = (self.lx as u16 + 8).saturating_sub(sprite.x_pos as u16) as u8;
+        for x in skip..8 {
+            let color = if sprite.attrs & SPRITE_X_FLIP != 0 {
+                row_color(low, high, 7 - x)
             } else {
-                // Only compute BG if Window pixel is off
-                let bg_x = (scx as u16 + x_screen as u16) % 256;
-                let bg_y = (scy as u16 + ly as u16) % 256;
-                let t_x = bg_x / 8;
-                let t_y = bg_y / 8;
-                let i_in_tmap = (t_y * 32) as u16 + t_x as u16;
-                let tile_index = game_state.get_tile_index(i_in_tmap);
-                let x_tile = (bg_x % 8) as u8;
-                let y_tile = (bg_y % 8) as u8;
-                final_pix = get_tile_pixel(lcdc, tile_index, x_tile, y_tile, game_state, false);
+                row_color(low, high, x)
+            };
+            // Pixels already in the FIFO belong to sprites that take priority over this one
+            let slot = &mut self.obj_fifo[((self.obj_head + x - skip) % 8) as usize];
+            if slot.color == 0 && color != 0 {
+                *slot = ObjPixel {
+                    color,
+                    behind_bg: sprite.attrs & SPRITE_PRIORITY != 0,
+                    obp1: sprite.attrs & SPRITE_PALETTE != 0,
+                };
             }
+        }
+    }
 
-            for i in 0..10 {
-                if self.active_sprites[i].is_none() {
-                    continue;
-                }
-                let sprite_top = self.active_sprites[i].unwrap().y_pos - 16;
-                // println!("New sprite_top: {sprite_top}");
-                let sprite_left = self.active_sprites[i].unwrap().x_pos - 8;
-                let sprite_height = if game_state.get_lcdc() & LCDC_TILE_SIZE == 0 {
-                    7
-                } else {
-                    15
-                };
+    fn drawing_dot(&mut self, gb: &mut Gameboy) {
+        if self.sprite_fetch_dots > 0 {
+            self.sprite_fetch_dots -= 1;
+            if self.sprite_fetch_dots > 0 {
+                return;
+            }
+            self.fetch_sprite(gb);
+        }
 
-                if (sprite_top <= ly && ly <= sprite_top + sprite_height)
-                    && (sprite_left <= x_screen && x_screen <= sprite_left + 7)
-                {
-                    // sprite in line
-                    let attrs = self.active_sprites[i].unwrap().attrs;
-                    let y_flip = attrs & SPRITE_Y_FLIP != 0;
-                    let v_offset = if y_flip {
-                        sprite_height - (ly - sprite_top)
-                    } else {
-                        ly - sprite_top
-                    };
-                    let x_flip = attrs & SPRITE_X_FLIP != 0;
-                    let h_offset = if x_flip {
-                        7 - (x_screen - sprite_left)
-                    } else {
-                        x_screen - sprite_left
-                    };
-                    let tile_index;
-                    if sprite_height == 7 {
-                        tile_index = self.active_sprites[i].unwrap().tile_index;
-                    } else {
-                        tile_index = (self.active_sprites[i].unwrap().tile_index & 0b1111_1110)
-                            + (if v_offset >= 8 { 1 } else { 0 });
-                    }
+        // The window takes over by flushing the FIFO and restarting the fetcher on its tiles.
+        // At WX 0-6 it starts with the line, losing the columns that fall off the left edge
+        let wx = gb.get_wx();
+        let at_wx = if wx < 7 || self.window_wrap {
+            self.lx == 0
+        } else {
+            self.lx as u16 + 7 == wx as u16
+        };
+        if !self.in_window && at_wx && self.wy_triggered && gb.get_lcdc() & LCDC_WIN_ON != 0 {
+            self.in_window = true;
+            self.bg_len = 0;
+            self.fetcher_dot = 0;
+            self.fetcher_x = 0;
+            self.discard = if self.window_wrap {
+                0
+            } else {
+                7u8.saturating_sub(wx)
+            };
+            self.window_wrap = wx == 166 && self.lx > 0;
+        }
 
-                    let pix_val =
-                        get_tile_pixel(lcdc, tile_index, h_offset, v_offset, game_state, true);
-
-                    if pix_val != 0 {
-                        if attrs & SPRITE_PRIORITY == 0 {
-                            final_pix = pix_val;
-                        } else {
-                            if final_pix == 0 {
-                                final_pix = pix_val;
-                            }
-                        }
-                        break;
-                    }
-                }
+        self.fetcher_step(gb);
+        if self.bg_len == 0 {
+            return;
+        }
+
+        // A sprite waits for the fetcher to reach its last step, then holds up the pixels while
+        // its own row is fetched
+        if let Some(index) = self.sprite_at_lx(gb).filter(|_| self.discard == 0) {
+            if self.fetcher_dot >= FETCH_PUSH - 1 {
+                self.sprite_fetch_index = index;
+                self.sprite_fetch_dots = SPRITE_FETCH_DOTS;
             }
+            return;
+        }
 
-            result[x_screen as usize] = final_pix;
+        let bg = self.bg_fifo[8 - self.bg_len as usize];
+        self.bg_len -= 1;
+        let obj = std::mem::take(&mut self.obj_fifo[self.obj_head as usize]);
+        self.obj_head = (self.obj_head + 1) % 8;
+        if self.discard > 0 {
+            self.discard -= 1;
+            return;
         }
 
-        result
+        // On the DMG, clearing LCDC bit 0 blanks the background and window but not sprites
+        let lcdc = gb.get_lcdc();
+        let bg = if lcdc & LCDC_BG_ON == 0 { 0 } else { bg };
+        // Transparency and priority go by colour ID, before any palette is applied
+        let shade = if lcdc & LCDC_OBJ_ON != 0 && obj.color != 0 && (!obj.behind_bg || bg == 0) {
+            let palette = if obj.obp1 {
+                gb.get_obp1()
+            } else {
+                gb.get_obp0()
+            };
+            palette_shade(palette, obj.color)
+        } else {
+            palette_shade(gb.get_bgp(), bg)
+        };
+        if !self.blank_frame {
+            let ly = gb.get_ly() as usize;
+            self.current_fb[ly * 160 + self.lx as usize] = self.shades[shade as usize];
+        }
+        self.lx += 1;
+        if self.lx == 160 {
+            self.set_mode(Mode::HBlank, gb);
+        }
     }
 
-    // return true if new frame is ready
-    pub fn step(&mut self, cycles: u8, game_state: &mut GameState) -> bool {
-        self.dot_counter += cycles as u128;
-        while self.dot_counter >= DOTS_PER_SL as u128 {
-            self.dot_counter -= DOTS_PER_SL as u128;
-            let ly = game_state.get_ly();
-            if ly < VISIBLE_SL {
-                self.oam_scan(game_state);
-                let next_scanline = self.gen_scanline(game_state);
-
-                for i in 0..160 {
-                    self.current_fb[(ly as u16 * 160u16 + i) as usize] =
-                        gb_color_to_u32(next_scanline[i as usize]);
+    // Returns true when this dot finishes a frame
+    fn dot(&mut self, gb: &mut Gameboy) -> bool {
+        match self.mode {
+            Mode::OamScan => {
+                if self.line_dot % 2 == 1 {
+                    self.scan_entry((self.line_dot / 2) as u8, gb);
+                }
+                // Switching at the end of dot 79 makes dot 80 the first one drawn
+                if self.line_dot == OAM_SCAN_DOTS - 1 {
+                    self.start_drawing(gb);
                 }
             }
-            game_state.inc_ly(1);
-            self.reset_active_entries();
-            if ly + 1 == VISIBLE_SL {
-                // VBLANK
-                game_state.write(game_state.read(0xFF0F) | INT_VBLANK, 0xFF0F);
-                // println!("VBLANK");
-                return true;
+            Mode::Drawing => self.drawing_dot(gb),
+            Mode::HBlank => {
+                if self.lcd_starting && self.line_dot == OAM_SCAN_DOTS - 1 {
+                    self.lcd_starting = false;
+                    self.start_drawing(gb);
+                }
             }
+            Mode::VBlank => (),
+        }
 
-            if ly + 1 > MAX_SL {
-                game_state.set_ly(0);
-            }
+        self.line_dot += 1;
+        if self.line_dot < DOTS_PER_SL {
+            return false;
+        }
+        self.line_dot = 0;
+        if self.in_window {
+            self.window_line = self.window_line.wrapping_add(1);
+        } else {
+            self.window_wrap = false;
+        }
+        // inc_ly wraps from 153 back to 0
+        let ly = gb.get_ly();
+        gb.inc_ly(1);
+
+        if ly + 1 == VISIBLE_SL {
+            // VBLANK
+            self.set_mode(Mode::VBlank, gb);
+            self.blank_frame = false;
+            self.in_window = false;
+            self.window_wrap = false;
+            self.window_line = 0;
+            self.wy_triggered = false;
+            gb.request_interrupt(INT_VBLANK);
+            return true;
+        }
+        if gb.get_ly() < VISIBLE_SL {
+            self.set_mode(Mode::OamScan, gb);
+            self.sprite_count = 0;
         }
         false
     }
+
+    // return true if new frame is ready
+    pub fn step(&mut self, cycles: u8, gb: &mut Gameboy) -> bool {
+        if gb.get_lcdc() & LCDC_ON == 0 {
+            return false;
+        }
+        let mut frame_ready = false;
+        for _ in 0..cycles {
+            frame_ready |= self.dot(gb);
+        }
+        frame_ready
+    }
 }
@@ -0,0 +1,413 @@
+// Reference Manual - https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+use crate::savestate::{StateReader, StateWriter};
+use std::io::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const RTC_SECONDS: u8 = 0x08;
+pub const RTC_MINUTES: u8 = 0x09;
+pub const RTC_HOURS: u8 = 0x0A;
+pub const RTC_DAY_LOW: u8 = 0x0B;
+pub const RTC_DAY_HIGH: u8 = 0x0C;
+
+const DAY_HIGH_BIT: u8 = 0b0000_0001;
+const HALT_BIT: u8 = 0b0100_0000;
+const CARRY_BIT: u8 = 0b1000_0000;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// BGB/VBA-M append the clock to the .sav as ten little-endian u32 registers and a timestamp,
+// older versions wrote the timestamp as a u32
+pub const RTC_FOOTER_SIZE: usize = 48;
+pub const RTC_FOOTER_SIZE_32: usize = 44;
+
+// Seconds since the Unix epoch, swapped out so tests can control the passage of time
+pub trait TimeSource {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days: u16,
+    pub halted: bool,
+    pub carry: bool,
+}
+
+impl RtcRegisters {
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg {
+            RTC_SECONDS => self.seconds,
+            RTC_MINUTES => self.minutes,
+            RTC_HOURS => self.hours,
+            RTC_DAY_LOW => (self.days & 0xFF) as u8,
+            RTC_DAY_HIGH => {
+                let mut dh = (self.days >> 8) as u8 & DAY_HIGH_BIT;
+                if self.halted {
+                    dh |= HALT_BIT;
+                }
+                if self.carry {
+                    dh |= CARRY_BIT;
+                }
+                dh
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, reg: u8, value: u8) {
+        match reg {
+            RTC_SECONDS => self.seconds = value & 0x3F,
+            RTC_MINUTES => self.minutes = value & 0x3F,
+            RTC_HOURS => self.hours = value & 0x1F,
+            RTC_DAY_LOW => self.days = (self.days & 0x100) | value as u16,
+            RTC_DAY_HIGH => {
+                self.days = (self.days & 0xFF) | (((value & DAY_HIGH_BIT) as u16) << 8);
+                self.halted = value & HALT_BIT != 0;
+                self.carry = value & CARRY_BIT != 0;
+            }
+            _ => (),
+        }
+    }
+
+    fn save(&self, out: &mut Vec<u8>) {
+        for reg in RTC_SECONDS..=RTC_DAY_HIGH {
+            out.extend_from_slice(&(self.read(reg) as u32).to_le_bytes());
+        }
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        for (reg, word) in (RTC_SECONDS..=RTC_DAY_HIGH).zip(data.chunks_exact(4)) {
+            self.write(reg, word[0]);
+        }
+    }
+
+    fn in_range(&self) -> bool {
+        self.seconds < 60 && self.minutes < 60 && self.hours < 24
+    }
+
+    // Counters hold out-of-range values written by the game until they wrap on their own
+    fn tick(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3F;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3F;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1F;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.add_days(1);
+    }
+
+    fn add_days(&mut self, days: u64) {
+        let total = self.days as u64 + days;
+        if total > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (total & 0x1FF) as u16;
+    }
+
+    fn advance(&mut self, mut secs: u64) {
+        while secs > 0 && !self.in_range() {
+            self.tick();
+            secs -= 1;
+        }
+        if secs == 0 {
+            return;
+        }
+
+        let time_of_day =
+            self.hours as u64 * 3600 + self.minutes as u64 * 60 + self.seconds as u64 + secs;
+        self.seconds = (time_of_day % 60) as u8;
+        self.minutes = (time_of_day / 60 % 60) as u8;
+        self.hours = (time_of_day / 3600 % 24) as u8;
+        self.add_days(time_of_day / SECONDS_PER_DAY);
+    }
+}
+
+pub struct Rtc {
+    pub registers: RtcRegisters,
+    pub latched: RtcRegisters,
+    // Time source reading at which `registers` were last brought up to date
+    pub last_update: u64,
+    time_source: Box<dyn TimeSource>,
+}
+
+impl Rtc {
+    pub fn with_time_source(time_source: Box<dyn TimeSource>) -> Self {
+        let last_update = time_source.now();
+        Self {
+            registers: RtcRegisters::default(),
+            latched: RtcRegisters::default(),
+            last_update,
+            time_source,
+        }
+    }
+
+    pub fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.update();
+        self.last_update = time_source.now();
+        self.time_source = time_source;
+    }
+
+    pub fn swap_time_source(&mut self, other: &mut Rtc) {
+        std::mem::swap(&mut self.time_source, &mut other.time_source);
+    }
+
+    // Catch the counters up with however much time has passed since the last update
+    pub fn update(&mut self) {
+        let now = self.time_source.now();
+        if !self.registers.halted {
+            self.registers.advance(now.saturating_sub(self.last_update));
+        }
+        self.last_update = now;
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.registers;
+    }
+
+    pub fn save_footer(&mut self) -> Vec<u8> {
+        self.update();
+        let mut footer = Vec::with_capacity(RTC_FOOTER_SIZE);
+        self.registers.save(&mut footer);
+        self.latched.save(&mut footer);
+        footer.extend_from_slice(&self.last_update.to_le_bytes());
+        footer
+    }
+
+    // Restores the clock and then runs it forward by the time that passed since it was saved
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        self.registers.load(&footer[0..20]);
+        self.latched.load(&footer[20..40]);
+        let mut timestamp = [0; 8];
+        let len = footer.len().min(RTC_FOOTER_SIZE) - 40;
+        timestamp[..len].copy_from_slice(&footer[40..40 + len]);
+        self.last_update = u64::from_le_bytes(timestamp);
+        self.update();
+    }
+
+    // Like the .sav footer, a loaded state catches up with the real time that passed since
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        w.bytes(&self.save_footer());
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.load_footer(r.bytes(RTC_FOOTER_SIZE)?);
+        Ok(())
+    }
+
+    pub fn read(&self, reg: u8) -> u8 {
+        self.latched.read(reg)
+    }
+
+    // Returns true if a register changed
+    pub fn write(&mut self, reg: u8, value: u8) -> bool {
+        self.update();
+        let before = (self.registers, self.latched);
+        self.registers.write(reg, value);
+        // Writes are visible straight away on hardware without re-latching
+        self.latched.write(reg, value);
+        (self.registers, self.latched) != before
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::{Cartridge, CartridgeHeader};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // A clock that only moves when the test says so
+    #[derive(Clone, Default)]
+    struct FakeClock(Rc<Cell<u64>>);
+
+    impl FakeClock {
+        fn advance(&self, secs: u64) {
+            self.0.set(self.0.get() + secs);
+        }
+    }
+
+    impl TimeSource for FakeClock {
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn rtc_at(start: u64) -> (Rtc, FakeClock) {
+        let clock = FakeClock(Rc::new(Cell::new(start)));
+        (Rtc::with_time_source(Box::new(clock.clone())), clock)
+    }
+
+    fn time(hours: u8, minutes: u8, seconds: u8, days: u16) -> RtcRegisters {
+        RtcRegisters {
+            seconds,
+            minutes,
+            hours,
+            days,
+            ..RtcRegisters::default()
+        }
+    }
+
+    // MBC3+TIMER+RAM+BATTERY
+    fn mbc3_rom() -> Vec<u8> {
+        CartridgeHeader::test_rom(0x10, 0x02, &[])
+    }
+
+    #[test]
+    fn counters_roll_over_into_the_next_unit() {
+        let (mut rtc, clock) = rtc_at(1000);
+        rtc.registers = time(0, 0, 59, 0);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers, time(0, 1, 0, 0));
+
+        rtc.registers = time(0, 59, 59, 0);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers, time(1, 0, 0, 0));
+
+        rtc.registers = time(23, 59, 59, 41);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers, time(0, 0, 0, 42));
+
+        // Several units at once
+        clock.advance(2 * SECONDS_PER_DAY + 3 * 3600 + 4 * 60 + 5);
+        rtc.update();
+        assert_eq!(rtc.registers, time(3, 4, 5, 44));
+    }
+
+    #[test]
+    fn out_of_range_values_count_up_until_they_wrap() {
+        let (mut rtc, clock) = rtc_at(0);
+        rtc.write(RTC_SECONDS, 62);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers.seconds, 63);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers.seconds, 0);
+        assert_eq!(rtc.registers.minutes, 0);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let (mut rtc, clock) = rtc_at(0);
+        rtc.registers = time(23, 59, 59, 0x1FF);
+        clock.advance(1);
+        rtc.update();
+        assert_eq!(rtc.registers.days, 0);
+        assert!(rtc.registers.carry);
+        rtc.latch();
+        assert_eq!(rtc.read(RTC_DAY_HIGH) & CARRY_BIT, CARRY_BIT);
+        assert_eq!(rtc.read(RTC_DAY_HIGH) & DAY_HIGH_BIT, 0);
+
+        // Carry sticks until the game clears it
+        clock.advance(SECONDS_PER_DAY);
+        rtc.update();
+        assert!(rtc.registers.carry);
+        rtc.write(RTC_DAY_HIGH, 0);
+        assert!(!rtc.registers.carry);
+    }
+
+    #[test]
+    fn halt_freezes_the_clock() {
+        let (mut rtc, clock) = rtc_at(0);
+        rtc.registers = time(1, 2, 3, 4);
+        rtc.write(RTC_DAY_HIGH, HALT_BIT);
+        clock.advance(1000);
+        rtc.update();
+        assert_eq!(rtc.registers.seconds, 3);
+        assert_eq!(rtc.registers.minutes, 2);
+
+        // Time spent halted is never caught up on
+        rtc.write(RTC_DAY_HIGH, 0);
+        clock.advance(5);
+        rtc.update();
+        assert_eq!(rtc.registers, time(1, 2, 8, 4));
+    }
+
+    #[test]
+    fn writes_report_whether_a_register_changed() {
+        let (mut rtc, _clock) = rtc_at(0);
+        assert!(rtc.write(RTC_SECONDS, 30));
+        assert!(!rtc.write(RTC_SECONDS, 30));
+        assert!(rtc.write(RTC_DAY_HIGH, HALT_BIT));
+        assert!(!rtc.write(RTC_DAY_HIGH, HALT_BIT));
+    }
+
+    #[test]
+    fn writing_0_then_1_to_0x6000_latches_the_clock() {
+        let clock = FakeClock::default();
+        let mut cart = Cartridge::from_bytes(mbc3_rom()).unwrap();
+        cart.set_time_source(Box::new(clock.clone()));
+        cart.write(0x0A, 0x0000);
+        cart.write(RTC_SECONDS, 0x4000);
+
+        clock.advance(5);
+        assert_eq!(cart.read(0xA000), 0);
+        cart.write(0x00, 0x6000);
+        cart.write(0x01, 0x6000);
+        assert_eq!(cart.read(0xA000), 5);
+
+        // The latched copy holds still while the clock runs on
+        clock.advance(3);
+        assert_eq!(cart.read(0xA000), 5);
+        // A 1 that doesn't follow a 0 doesn't latch
+        cart.write(0x01, 0x6000);
+        assert_eq!(cart.read(0xA000), 5);
+        cart.write(0x02, 0x6000);
+        cart.write(0x01, 0x6000);
+        assert_eq!(cart.read(0xA000), 5);
+
+        cart.write(0x00, 0x6000);
+        cart.write(0x01, 0x6000);
+        assert_eq!(cart.read(0xA000), 8);
+    }
+
+    #[test]
+    fn footer_round_trip_catches_up_with_wall_clock_time() {
+        let (mut rtc, clock) = rtc_at(1_000_000);
+        rtc.registers = time(10, 20, 30, 40);
+        rtc.latch();
+        let footer = rtc.save_footer();
+        assert_eq!(footer.len(), RTC_FOOTER_SIZE);
+
+        clock.advance(3600 + 5);
+        let (mut loaded, _) = rtc_at(clock.now());
+        loaded.load_footer(&footer);
+        assert_eq!(loaded.registers, time(11, 20, 35, 40));
+        assert_eq!(loaded.latched, time(10, 20, 30, 40));
+        assert_eq!(loaded.last_update, clock.now());
+
+        // Older 32-bit timestamps load the same way
+        let (mut loaded, _) = rtc_at(clock.now());
+        loaded.load_footer(&footer[..RTC_FOOTER_SIZE_32]);
+        assert_eq!(loaded.registers, time(11, 20, 35, 40));
+    }
+}
@@ -0,0 +1,313 @@
+// Reference Manual - https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+use crate::savestate::{StateReader, StateWriter};
+use std::io::{Read, Result, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::Duration;
+
+const SC_TRANSFER: u8 = 0b1000_0000;
+const SC_INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+// What the other end of the cable connects to. Bytes are traded whole rather than bit by bit,
+// since that's all a game can observe
+pub trait SerialLink {
+    // This side drives the clock: sends byte and returns the one shifted in from the other end,
+    // which is 0xFF when nothing answers
+    fn transfer(&mut self, byte: u8) -> u8;
+
+    // The other side drives the clock: if it has sent a byte, answers with reply and returns it
+    fn receive(&mut self, reply: u8) -> Option<u8>;
+}
+
+// A cable plugged back into the same Game Boy, so every byte comes straight back
+pub struct Loopback;
+
+impl SerialLink for Loopback {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        byte
+    }
+
+    fn receive(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+}
+
+// Prints every byte sent as text, which is how test ROMs like Blargg's report their results
+pub struct StdoutLink;
+
+impl SerialLink for StdoutLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+        0xFF
+    }
+
+    fn receive(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+}
+
+// Over the socket every byte is a three byte message: whether it's a clocked byte or the reply to
+// one, the sequence number of the clocked byte, then the byte itself. A reply carries the number
+// of the byte it answers, so one that turns up after its transfer timed out can't be mistaken
+// for the answer to the next
+const MSG_CLOCKED: u8 = 0;
+const MSG_REPLY: u8 = 1;
+
+// Long enough to ride out a slow frame on the other end, short enough that a closed instance
+// doesn't hang this one
+const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Links two emulator instances over a TCP or Unix socket
+pub struct SocketLink {
+    writer: Box<dyn Write + Send>,
+    incoming: Receiver<[u8; 3]>,
+    seq: u8,
+}
+
+impl SocketLink {
+    pub fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    // Blocks until the other instance connects
+    pub fn listen_tcp(addr: impl ToSocketAddrs) -> Result<Self> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<Path>) -> Result<Self> {
+        let stream = UnixStream::connect(path)?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    // Blocks until the other instance connects
+    #[cfg(unix)]
+    pub fn listen_unix(path: impl AsRef<Path>) -> Result<Self> {
+        let (stream, _) = UnixListener::bind(path)?.accept()?;
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+
+    // Incoming messages are read on their own thread so polling for them never blocks
+    pub fn new(
+        mut reader: impl Read + Send + 'static,
+        writer: impl Write + Send + 'static,
+    ) -> Self {
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut msg = [0; 3];
+            while reader.read_exact(&mut msg).is_ok() && sender.send(msg).is_ok() {}
+        });
+        Self {
+            writer: Box::new(writer),
+            incoming,
+            seq: 0,
+        }
+    }
+
+    fn send(&mut self, kind: u8, seq: u8, byte: u8) -> Result<()> {
+        self.writer.write_all(&[kind, seq, byte])?;
+        self.writer.flush()
+    }
+}
+
+impl SerialLink for SocketLink {
+    // Runs on the emulation thread and waits for the reply, so a slow peer stalls this instance
+    // by up to REPLY_TIMEOUT for every byte it clocks
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.seq = self.seq.wrapping_add(1);
+        if self.send(MSG_CLOCKED, self.seq, byte).is_err() {
+            return 0xFF;
+        }
+        loop {
+            match self.incoming.recv_timeout(REPLY_TIMEOUT) {
+                Ok([MSG_REPLY, seq, reply]) if seq == self.seq => return reply,
+                // Late reply to an earlier transfer
+                Ok([MSG_REPLY, _, _]) => (),
+                // Both ends started a transfer at once, neither of them gets real data
+                Ok([_, seq, _]) => {
+                    let _ = self.send(MSG_REPLY, seq, 0xFF);
+                }
+                Err(_) => return 0xFF,
+            }
+        }
+    }
+
+    fn receive(&mut self, reply: u8) -> Option<u8> {
+        // Replies that turn up after their transfer timed out are dropped
+        while let Ok([kind, seq, byte]) = self.incoming.try_recv() {
+            if kind == MSG_CLOCKED {
+                let _ = self.send(MSG_REPLY, seq, reply);
+                return Some(byte);
+            }
+        }
+        None
+    }
+}
+
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    // Bits of the current internally clocked transfer still to shift, and what they shift in
+    bits_left: u8,
+    incoming: u8,
+    link: Option<Box<dyn SerialLink>>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Self {
+            sb: 0,
+            sc: 0,
+            bits_left: 0,
+            incoming: 0,
+            link: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            link: self.link.take(),
+            ..Self::new()
+        };
+    }
+
+    pub fn set_link(&mut self, link: Box<dyn SerialLink>) {
+        self.link = Some(link);
+    }
+
+    // Takes on the state `loaded` was given by load_state, keeping the cable plugged in
+    pub fn restore(&mut self, loaded: Self) {
+        *self = Self {
+            link: self.link.take(),
+            ..loaded
+        };
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF01 => self.sb,
+            // Bits 1-6 aren't wired up on the DMG
+            0xFF02 => self.sc | 0b0111_1110,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, value: u8, addr: u16) {
+        match addr {
+            0xFF01 => self.sb = value,
+            0xFF02 => {
+                self.sc = value & (SC_TRANSFER | SC_INTERNAL_CLOCK);
+                if self.sc == SC_TRANSFER | SC_INTERNAL_CLOCK {
+                    // The other end's byte is fetched up front and shifted in as the clock runs
+                    self.bits_left = 8;
+                    self.incoming = match &mut self.link {
+                        Some(link) => link.transfer(self.sb),
+                        None => 0xFF,
+                    };
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Runs on every tick of the 8192 Hz serial clock and returns true when a transfer completes
+    pub fn clock(&mut self) -> bool {
+        if self.sc & SC_TRANSFER == 0 {
+            return false;
+        }
+        if self.sc & SC_INTERNAL_CLOCK == 0 {
+            let received = self.link.as_mut().and_then(|link| link.receive(self.sb));
+            return match received {
+                Some(byte) => {
+                    self.sb = byte;
+                    self.sc &= !SC_TRANSFER;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        self.sb = (self.sb << 1) | (self.incoming >> 7);
+        self.incoming <<= 1;
+        self.bits_left = self.bits_left.saturating_sub(1);
+        if self.bits_left == 0 {
+            self.sc &= !SC_TRANSFER;
+        }
+        self.bits_left == 0
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.sb);
+        w.u8(self.sc);
+        w.u8(self.bits_left);
+        w.u8(self.incoming);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.sb = r.u8()?;
+        self.sc = r.u8()?;
+        self.bits_left = r.u8()?;
+        self.incoming = r.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn linked_pair() -> (SocketLink, SocketLink) {
+        let (a, b) = UnixStream::pair().unwrap();
+        (
+            SocketLink::new(a.try_clone().unwrap(), a),
+            SocketLink::new(b.try_clone().unwrap(), b),
+        )
+    }
+
+    // Answers the next clocked byte from another thread, as the other instance would
+    fn answer(mut link: SocketLink, reply: u8) -> thread::JoinHandle<(SocketLink, u8)> {
+        thread::spawn(move || loop {
+            if let Some(byte) = link.receive(reply) {
+                return (link, byte);
+            }
+            thread::sleep(Duration::from_millis(1));
+        })
+    }
+
+    #[test]
+    fn bytes_are_exchanged_both_ways() {
+        let (mut a, b) = linked_pair();
+        let peer = answer(b, 0x34);
+        assert_eq!(a.transfer(0x12), 0x34);
+        assert_eq!(peer.join().unwrap().1, 0x12);
+    }
+
+    #[test]
+    fn late_replies_are_not_taken_for_the_next_byte() {
+        let (mut a, mut b) = linked_pair();
+        assert_eq!(a.transfer(0x12), 0xFF);
+        // The other end only gets round to answering once the transfer has given up
+        assert_eq!(b.receive(0x34), Some(0x12));
+
+        let peer = answer(b, 0x78);
+        assert_eq!(a.transfer(0x56), 0x78);
+        let (mut b, byte) = peer.join().unwrap();
+        assert_eq!(byte, 0x56);
+
+        let peer = answer(b, 0x9A);
+        assert_eq!(a.transfer(0xBC), 0x9A);
+        b = peer.join().unwrap().0;
+        assert_eq!(b.receive(0), None);
+    }
+}
@@ -1,5 +1,13 @@
+use crate::apu::{APU, DEFAULT_SAMPLE_RATE};
+use crate::boot::{BootRom, Model};
+use crate::cartridge::{Cartridge, CartridgeHeader, ImageSource};
 use crate::constants::*;
-use std::fs;
+use crate::ppu::PPU;
+use crate::rtc::TimeSource;
+use crate::savestate::{invalid_state, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+use crate::serial::{Serial, SerialLink};
+use std::io::Result;
+use std::path::PathBuf;
 
 fn generate_16bit(lsb: u8, msb: u8) -> u16 {
     ((msb as u16) << 8) | (lsb as u16)
@@ -41,6 +49,39 @@ struct Joypad {
     right_button: bool,
 }
 
+impl Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        for button in [
+            self.a_button,
+            self.b_button,
+            self.start_button,
+            self.select_button,
+            self.up_button,
+            self.down_button,
+            self.left_button,
+            self.right_button,
+        ] {
+            w.bool(button);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        for button in [
+            &mut self.a_button,
+            &mut self.b_button,
+            &mut self.start_button,
+            &mut self.select_button,
+            &mut self.up_button,
+            &mut self.down_button,
+            &mut self.left_button,
+            &mut self.right_button,
+        ] {
+            *button = r.bool()?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Flags {
     pub Z: bool,
     pub N: bool,
@@ -48,11 +89,28 @@ pub struct Flags {
     pub C: bool,
 }
 
+// The frame sequencer ticks on falling edges of bit 4 of the DIV register
+const DIV_APU_BIT: u16 = 0b0001_0000_0000_0000;
+// and the serial clock on falling edges of bit 8 of the counter beneath it, at 8192 Hz
+const DIV_SERIAL_BIT: u16 = 0b0000_0001_0000_0000;
+
 const FLAG_Z: u8 = 0b1000_0000;
 const FLAG_N: u8 = 0b0100_0000;
 const FLAG_H: u8 = 0b0010_0000;
 const FLAG_C: u8 = 0b0001_0000;
 
+// The CGB boot ROM sums the title of Nintendo-published games to pick a compatibility palette,
+// and leaves the sum in B
+fn cgb_title_checksum(cart: &Cartridge) -> u8 {
+    let old_licensee = cart.read(0x014B);
+    let new_licensee = [cart.read(0x0144), cart.read(0x0145)];
+    if old_licensee == 0x01 || (old_licensee == 0x33 && new_licensee == *b"01") {
+        (0x0134..=0x0143).fold(0u8, |sum, addr| sum.wrapping_add(cart.read(addr)))
+    } else {
+        0
+    }
+}
+
 struct Registers {
     a: u8,
     f: u8,
@@ -67,20 +125,81 @@ struct Registers {
 }
 
 impl Registers {
-    fn reset_registers() -> Self {
+    fn power_on() -> Self {
         Self {
-            a: 0x01,
-            f: 0xB0,
-            b: 0x00,
-            c: 0x13,
-            d: 0x00,
-            e: 0xD8,
-            h: 0x01,
-            l: 0x4D,
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            pc: 0x0000,
+            sp: 0x0000,
+        }
+    }
+
+    fn post_boot(model: Model, cart: &Cartridge) -> Self {
+        // H and C are left over from the boot ROM's header checksum
+        let f = if cart.header.header_checksum == 0 {
+            FLAG_Z
+        } else {
+            FLAG_Z | FLAG_H | FLAG_C
+        };
+        let (a, f, bc, de, hl) = match model {
+            Model::Dmg => (0x01, f, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFF, f, 0x0013, 0x00D8, 0x014D),
+            // Only DMG mode is emulated, so a CGB runs every cartridge in compatibility mode
+            Model::Cgb => {
+                let b = cgb_title_checksum(cart);
+                let hl = if b == 0x43 || b == 0x58 {
+                    0x991A
+                } else {
+                    0x007C
+                };
+                (0x11, FLAG_Z, (b as u16) << 8, 0x0008, hl)
+            }
+        };
+        Self {
+            a,
+            f,
+            b: (bc >> 8) as u8,
+            c: bc as u8,
+            d: (de >> 8) as u8,
+            e: de as u8,
+            h: (hl >> 8) as u8,
+            l: hl as u8,
             pc: 0x0100,
             sp: 0xFFFE,
         }
     }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&[
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ]);
+        w.u16(self.pc);
+        w.u16(self.sp);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        for reg in [
+            &mut self.a,
+            &mut self.f,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *reg = r.u8()?;
+        }
+        self.pc = r.u16()?;
+        self.sp = r.u16()?;
+        Ok(())
+    }
 }
 
 // TODO Finish this struct
@@ -100,7 +219,24 @@ struct IORegisters {
 }
 
 impl IORegisters {
-    fn reset_registers() -> Self {
+    fn power_on() -> Self {
+        Self {
+            joyp: 0xCF,
+            lcdc: 0,
+            ly: 0,
+            lyc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            wy: 0,
+            wx: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+        }
+    }
+
+    fn post_boot() -> Self {
         Self {
             joyp: 0xCF,
             lcdc: 0x91,
@@ -116,24 +252,107 @@ impl IORegisters {
             obp1: 0xFF,
         }
     }
+
+    fn fields(&mut self) -> [&mut u8; 12] {
+        [
+            &mut self.joyp,
+            &mut self.lcdc,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.wy,
+            &mut self.wx,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+        ]
+    }
+
+    fn save_state(&mut self, w: &mut StateWriter) {
+        for reg in self.fields() {
+            w.u8(*reg);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        for reg in self.fields() {
+            *reg = r.u8()?;
+        }
+        Ok(())
+    }
 }
 
+// DIV is the top byte of a 16-bit counter that runs every T-cycle, and TIMA counts the falling
+// edges of one of its bits
 struct TimerRegisters {
     div: u16,
     tima: u8,
     tma: u8,
     tac: u8,
+    // TIMA reads 0 for the M-cycle after it overflows, then TMA is loaded and the interrupt fires
+    overflowed: bool,
+    // Set for the M-cycle the reload happens in, when TIMA writes are lost and TMA writes go
+    // through to TIMA too
+    reloading: bool,
 }
 
 impl TimerRegisters {
-    fn reset_registers() -> Self {
+    fn power_on() -> Self {
         Self {
             div: 0x0000,
             tima: 0x00,
             tma: 0x00,
-            tac: 0xF8,
+            tac: 0x00,
+            overflowed: false,
+            reloading: false,
+        }
+    }
+
+    // The CGB's DIV isn't documented, so every model uses the DMG's
+    fn post_boot() -> Self {
+        Self {
+            div: 0xABCC,
+            ..Self::power_on()
         }
     }
+
+    // The TAC-selected counter bit ANDed with the enable bit, which is what TIMA watches
+    fn tima_signal(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+        self.tac & 0b100 != 0 && (self.div >> bit) & 1 != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, overflowed) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.overflowed = overflowed;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.div);
+        w.u8(self.tima);
+        w.u8(self.tma);
+        w.u8(self.tac);
+        w.bool(self.overflowed);
+        w.bool(self.reloading);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.div = r.u16()?;
+        self.tima = r.u8()?;
+        self.tma = r.u8()?;
+        self.tac = r.u8()?;
+        self.overflowed = r.bool()?;
+        self.reloading = r.bool()?;
+        Ok(())
+    }
 }
 
 struct Memory {
@@ -152,30 +371,83 @@ impl Memory {
             hram: [0; 0x7F],
         }
     }
+
+    // The DMG and MGB boot ROMs leave the logo from the cartridge header in VRAM, each pixel
+    // doubled, followed by the (R) symbol
+    fn load_boot_logo(&mut self, logo: &[u8]) {
+        const REGISTERED: [u8; 8] = [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C];
+        let double = |nibble: u8| {
+            (0..4).fold(0u8, |acc, bit| {
+                acc | ((nibble >> bit & 1) * 0b11) << (2 * bit)
+            })
+        };
+        for (i, byte) in logo.iter().enumerate() {
+            for (half, nibble) in [byte >> 4, byte & 0x0F].into_iter().enumerate() {
+                let row = 0x10 + i * 8 + half * 4;
+                self.vram[row] = double(nibble);
+                self.vram[row + 2] = double(nibble);
+            }
+        }
+        for (i, row) in REGISTERED.into_iter().enumerate() {
+            self.vram[0x190 + i * 2] = row;
+        }
+        // Tiles 1-12 and 13-24 make the logo's two rows, with the (R) at the end of the first
+        for tile in 0..12 {
+            self.vram[0x1904 + tile] = tile as u8 + 1;
+            self.vram[0x1924 + tile] = tile as u8 + 13;
+        }
+        self.vram[0x1910] = 0x19;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.wram);
+        w.bytes(&self.vram);
+        w.bytes(&self.oam);
+        w.bytes(&self.hram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        r.fill(&mut self.wram)?;
+        r.fill(&mut self.vram)?;
+        r.fill(&mut self.oam)?;
+        r.fill(&mut self.hram)
+    }
 }
 
-struct Gameboy {
+pub struct Gameboy {
     ime: bool,
+    ei_pending: bool,
     i_enable: u8,
     i_flag: u8,
     dma: u8,
+    dma_starting: bool,
+    dma_active: bool,
+    dma_index: u8,
     joypad: Joypad,
     registers: Registers,
     io_registers: IORegisters,
     timer_registers: TimerRegisters,
-    available_cycles: u16,
     memory: Memory,
     pc_moved: bool,
+    halted: bool,
+    halt_bug: bool,
+    stopped: bool,
     cycles: u128,
+    // The OR of the conditions selected in STAT, which raises the LCD interrupt when it goes high
+    stat_line: bool,
 }
 
 impl Gameboy {
-    fn reset_gb() -> Self {
+    fn power_on() -> Self {
         Self {
             ime: false,
+            ei_pending: false,
             i_enable: 0,
             i_flag: 0,
             dma: 0,
+            dma_starting: false,
+            dma_active: false,
+            dma_index: 0,
             joypad: Joypad {
                 a_button: false,
                 b_button: false,
@@ -186,61 +458,347 @@ impl Gameboy {
                 left_button: false,
                 right_button: false,
             },
-            registers: Registers::reset_registers(),
-            io_registers: IORegisters::reset_registers(),
-            timer_registers: TimerRegisters::reset_registers(),
-            available_cycles: 0,
+            registers: Registers::power_on(),
+            io_registers: IORegisters::power_on(),
+            timer_registers: TimerRegisters::power_on(),
             memory: Memory::reset_memory(),
             pc_moved: false,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
             cycles: 0,
+            stat_line: false,
         }
     }
-}
 
-enum MbcType {
-    RomOnly,
-    Mbc1,
-    Mbc2,
-    Mbc3,
-    Mbc5,
-    Unknown,
-}
+    // Where the boot ROM leaves the machine when it jumps to the cartridge
+    fn post_boot(model: Model, cart: &Cartridge) -> Self {
+        let mut gb = Self {
+            i_flag: INT_VBLANK,
+            dma: if model == Model::Cgb { 0x00 } else { 0xFF },
+            registers: Registers::post_boot(model, cart),
+            io_registers: IORegisters::post_boot(),
+            timer_registers: TimerRegisters::post_boot(),
+            ..Self::power_on()
+        };
+        if model != Model::Cgb {
+            let logo: Vec<u8> = (0x0104..0x0134).map(|addr| cart.read(addr)).collect();
+            gb.memory.load_boot_logo(&logo);
+        }
+        gb
+    }
 
-struct Cartridge {
-    rom: Vec<u8>,
-    ram: Vec<u8>,
-    mbc: MbcType,
-    current_bank: usize,
-}
+    fn save_state(&mut self, w: &mut StateWriter) {
+        w.bool(self.ime);
+        w.bool(self.ei_pending);
+        w.u8(self.i_enable);
+        w.u8(self.i_flag);
+        w.u8(self.dma);
+        w.bool(self.dma_starting);
+        w.bool(self.dma_active);
+        w.u8(self.dma_index);
+        self.joypad.save_state(w);
+        self.registers.save_state(w);
+        self.io_registers.save_state(w);
+        self.timer_registers.save_state(w);
+        self.memory.save_state(w);
+        w.bool(self.pc_moved);
+        w.bool(self.halted);
+        w.bool(self.halt_bug);
+        w.bool(self.stopped);
+        w.u128(self.cycles);
+        w.bool(self.stat_line);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        self.ime = r.bool()?;
+        self.ei_pending = r.bool()?;
+        self.i_enable = r.u8()?;
+        self.i_flag = r.u8()?;
+        self.dma = r.u8()?;
+        self.dma_starting = r.bool()?;
+        self.dma_active = r.bool()?;
+        self.dma_index = r.u8()?;
+        self.joypad.load_state(r)?;
+        self.registers.load_state(r)?;
+        self.io_registers.load_state(r)?;
+        self.timer_registers.load_state(r)?;
+        self.memory.load_state(r)?;
+        self.pc_moved = r.bool()?;
+        self.halted = r.bool()?;
+        self.halt_bug = r.bool()?;
+        self.stopped = r.bool()?;
+        self.cycles = r.u128()?;
+        self.stat_line = r.bool()?;
+        Ok(())
+    }
+
+    // Accessors for the PPU, which only sees this half of the machine
+    pub fn get_oam_entry(&self, loc: u8) -> [u8; 4] {
+        let l = (loc % 0xA0) as usize;
+        return [
+            self.memory.oam[l],
+            self.memory.oam[l + 1],
+            self.memory.oam[l + 2],
+            self.memory.oam[l + 3],
+        ];
+    }
+
+    pub fn get_lcdc(&self) -> u8 {
+        return self.io_registers.lcdc;
+    }
+
+    pub fn get_ly(&self) -> u8 {
+        return self.io_registers.ly;
+    }
+
+    pub fn get_scx(&self) -> u8 {
+        return self.io_registers.scx;
+    }
+
+    pub fn get_scy(&self) -> u8 {
+        return self.io_registers.scy;
+    }
+
+    pub fn get_wx(&self) -> u8 {
+        return self.io_registers.wx;
+    }
+
+    pub fn get_wy(&self) -> u8 {
+        return self.io_registers.wy;
+    }
+
+    pub fn get_bgp(&self) -> u8 {
+        self.io_registers.bgp
+    }
+
+    pub fn get_obp0(&self) -> u8 {
+        self.io_registers.obp0
+    }
+
+    pub fn get_obp1(&self) -> u8 {
+        self.io_registers.obp1
+    }
+
+    pub fn inc_ly(&mut self, amount: u8) {
+        self.io_registers.ly = (self.io_registers.ly + amount) % 154;
+        self.update_stat();
+    }
+
+    pub fn set_ly(&mut self, val: u8) {
+        self.io_registers.ly = val;
+        self.update_stat();
+    }
+
+    pub fn set_stat_mode(&mut self, mode: u8) {
+        self.io_registers.stat = (self.io_registers.stat & !STAT_MODE) | mode;
+        self.update_stat();
+    }
+
+    // Brings the LY=LYC flag up to date and raises the LCD interrupt on a rising edge of the
+    // STAT line. While one selected condition holds the line stays high, which blocks the rest
+    fn update_stat(&mut self) {
+        let regs = &mut self.io_registers;
+        if regs.ly == regs.lyc {
+            regs.stat |= STAT_LYC_EQUAL;
+        } else {
+            regs.stat &= !STAT_LYC_EQUAL;
+        }
+        let line = match regs.stat & STAT_MODE {
+            0 => regs.stat & STAT_HBLANK_INT != 0,
+            1 => regs.stat & STAT_VBLANK_INT != 0,
+            2 => regs.stat & STAT_OAM_INT != 0,
+            _ => false,
+        } || (regs.stat & STAT_LYC_INT != 0 && regs.stat & STAT_LYC_EQUAL != 0);
+        if line && !self.stat_line {
+            self.request_interrupt(INT_LCD);
+        }
+        self.stat_line = line;
+    }
 
-impl Cartridge {
-    pub fn load_rom(path: &str) -> std::io::Result<Self> {
-        let rom = fs::read(path)?;
+    // map_bit is the LCDC bit that picks the map: LCDC_TILE_MAP for the background or
+    // LCDC_WIN_TILE_MAP for the window
+    pub fn get_tile_index(&self, map_bit: u8, tile_in_map: u16) -> u8 {
+        if self.io_registers.lcdc & map_bit == 0 {
+            return self.memory.vram[0x1800 + tile_in_map as usize];
+        } else {
+            return self.memory.vram[0x1C00 + tile_in_map as usize];
+        }
+    }
 
-        // TODO Read ROM header to figure out MBC type and RAM size
-        let mbc = MbcType::RomOnly;
-        let ram_size = 0x2000;
+    pub fn get_vram(&self, addr: u16) -> u8 {
+        self.memory.vram[(addr - 0x8000) as usize]
+    }
 
-        Ok(Self {
-            rom,
-            ram: vec![0; ram_size],
-            mbc,
-            current_bank: 1,
-        })
+    pub fn request_interrupt(&mut self, int: u8) {
+        self.i_flag |= int;
     }
 }
 
 pub struct GameState {
     gb: Gameboy,
     cart: Cartridge,
+    apu: APU,
+    ppu: PPU,
+    serial: Serial,
+    // Kept after it's unmapped so save states from while it was running can still be loaded
+    boot_rom: Option<BootRom>,
+    boot_rom_mapped: bool,
+    frame_ready: bool,
+    // M-cycles ticked so far by the instruction being executed
+    step_cycles: u8,
+    rumble_callback: Option<Box<dyn FnMut(bool)>>,
 }
 
 impl GameState {
     pub fn start_game(path: &str) -> std::io::Result<Self> {
-        Ok(Self {
-            gb: Gameboy::reset_gb(),
-            cart: Cartridge::load_rom(path)?,
-        })
+        Ok(Self::with_cartridge(Cartridge::load_rom(path)?))
+    }
+
+    pub fn start_game_saving_to(path: &str, save_path: PathBuf) -> std::io::Result<Self> {
+        Ok(Self::with_cartridge(Cartridge::load_rom_saving_to(
+            path, save_path,
+        )?))
+    }
+
+    pub fn from_rom(rom: Vec<u8>) -> std::io::Result<Self> {
+        Ok(Self::with_cartridge(Cartridge::from_bytes(rom)?))
+    }
+
+    fn with_cartridge(cart: Cartridge) -> Self {
+        Self {
+            gb: Gameboy::post_boot(Model::Dmg, &cart),
+            cart,
+            apu: APU::initialize(DEFAULT_SAMPLE_RATE),
+            ppu: PPU::initialize(),
+            serial: Serial::new(),
+            boot_rom: None,
+            boot_rom_mapped: false,
+            frame_ready: false,
+            step_cycles: 0,
+            rumble_callback: None,
+        }
+    }
+
+    // Snapshot of the whole machine, prefixed with a header naming the format version and ROM
+    pub fn save_state(&mut self, w: &mut StateWriter) {
+        w.bytes(STATE_MAGIC);
+        w.u32(STATE_VERSION);
+        w.u16(self.cart.rom_checksum());
+        self.gb.save_state(w);
+        w.bool(self.boot_rom_mapped);
+        self.apu.save_state(w);
+        self.ppu.save_state(w);
+        self.serial.save_state(w);
+        self.cart.save_state(w);
+    }
+
+    // Everything is parsed into temporaries before any of it replaces the running machine, so a
+    // state that turns out to be corrupt partway through leaves it as it was. The cartridge
+    // comes last and only swaps its state in once all of it has parsed
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<()> {
+        if r.bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(invalid_state("not a save state".to_string()));
+        }
+        let version = r.u32()?;
+        if version != STATE_VERSION {
+            return Err(invalid_state(format!(
+                "save state version {version} is not supported, expected {STATE_VERSION}"
+            )));
+        }
+        let checksum = r.u16()?;
+        if checksum != self.cart.rom_checksum() {
+            return Err(invalid_state(format!(
+                "save state was made with a different ROM (checksum 0x{checksum:04X})"
+            )));
+        }
+
+        let mut gb = Gameboy::power_on();
+        gb.load_state(r)?;
+        let boot_rom_mapped = r.bool()?;
+        if boot_rom_mapped && self.boot_rom.is_none() {
+            return Err(invalid_state(
+                "save state was made while a boot ROM was running".to_string(),
+            ));
+        }
+        let mut apu = APU::initialize(self.apu.sample_rate());
+        apu.load_state(r)?;
+        let mut ppu = PPU::initialize();
+        ppu.load_state(r)?;
+        let mut serial = Serial::new();
+        serial.load_state(r)?;
+        let cart = self.cart.load_state(r)?;
+        if !r.is_empty() {
+            return Err(invalid_state("save state has trailing data".to_string()));
+        }
+
+        self.gb = gb;
+        self.boot_rom_mapped = boot_rom_mapped;
+        self.apu.restore(apu);
+        self.ppu.restore(ppu);
+        self.serial.restore(serial);
+        self.cart.restore(cart);
+        Ok(())
+    }
+
+    // Starts the console over as model would be after its boot ROM. The cartridge keeps its
+    // mapper state and RAM
+    pub fn restart(&mut self, model: Model) {
+        self.gb = Gameboy::post_boot(model, &self.cart);
+        self.apu = APU::initialize(self.apu.sample_rate());
+        self.reset_common();
+        self.boot_rom = None;
+        self.boot_rom_mapped = false;
+    }
+
+    // Starts the console over from power-on with boot_rom covering the cartridge until it
+    // writes to 0xFF50
+    pub fn boot(&mut self, boot_rom: BootRom) {
+        self.gb = Gameboy::power_on();
+        self.apu = APU::initialize(self.apu.sample_rate());
+        self.apu.write(0x00, 0xFF26);
+        self.reset_common();
+        self.boot_rom = Some(boot_rom);
+        self.boot_rom_mapped = true;
+    }
+
+    fn reset_common(&mut self) {
+        self.ppu.reset();
+        self.serial.reset();
+        self.frame_ready = false;
+        self.step_cycles = 0;
+    }
+
+    pub fn cartridge_header(&self) -> &CartridgeHeader {
+        &self.cart.header
+    }
+
+    // Persists battery-backed cartridge RAM next to the ROM if it changed
+    pub fn flush_save(&mut self) -> std::io::Result<()> {
+        self.cart.flush_save()
+    }
+
+    // Replaces whatever is plugged into the link port
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink>) {
+        self.serial.set_link(link);
+    }
+
+    // Called with the new motor state whenever a rumble cartridge turns its motor on or off
+    pub fn set_rumble_callback(&mut self, callback: Box<dyn FnMut(bool)>) {
+        self.rumble_callback = Some(callback);
+    }
+
+    pub fn set_rtc_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.cart.set_time_source(time_source);
+    }
+
+    // Accelerometer input for MBC7 carts, in g with positive x to the right and y down
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.cart.set_tilt(x, y);
+    }
+
+    pub fn set_camera_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.cart.set_image_source(source);
     }
 
     pub fn get_register8(&self, reg: Register) -> u8 {
@@ -359,34 +917,50 @@ impl GameState {
         }
     }
 
+    // https://gbdev.io/pandocs/OAM_DMA_Transfer.html
     fn dma_oam(&mut self, value: u8) {
         self.gb.dma = value;
-        let start = (value as u16) << 8;
-        for i in 0..160 {
-            self.gb.memory.oam[i] = self.read(start + i as u16);
+        // A transfer already running carries on until the new one starts a cycle later
+        self.gb.dma_starting = true;
+    }
+
+    fn step_dma(&mut self) {
+        if self.gb.dma_active {
+            let mut src = ((self.gb.dma as u16) << 8) | self.gb.dma_index as u16;
+            // Sources past WRAM read its echo
+            if src >= 0xE000 {
+                src -= 0x2000;
+            }
+            self.gb.memory.oam[self.gb.dma_index as usize] = self.read(src);
+            self.gb.dma_index += 1;
+            self.gb.dma_active = self.gb.dma_index < 0xA0;
+        }
+        if self.gb.dma_starting {
+            self.gb.dma_starting = false;
+            self.gb.dma_active = true;
+            self.gb.dma_index = 0;
         }
     }
 
     pub fn read(&self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x3FFF => self.cart.rom[addr as usize],
-
-            0x4000..=0x7FFF => {
-                // TODO Implement Bank switching
-                self.cart.rom[addr as usize]
+            0x0000..=0x08FF if self.boot_rom_mapped => {
+                let boot_rom = self.boot_rom.as_ref().expect("a mapped boot ROM");
+                boot_rom.read(addr).unwrap_or_else(|| self.cart.read(addr))
             }
 
+            0x0000..=0x7FFF => self.cart.read(addr),
+
             0x8000..=0x9FFF => self.gb.memory.vram[addr as usize - 0x8000],
 
-            0xA000..=0xBFFF => {
-                // TODO External RAM
-                0xFF
-            }
+            0xA000..=0xBFFF => self.cart.read(addr),
 
             0xC000..=0xDFFF => self.gb.memory.wram[addr as usize - 0xC000],
 
             0xE000..=0xFDFF => self.gb.memory.wram[addr as usize - 0xE000],
 
+            // OAM belongs to the DMA unit while a transfer runs
+            0xFE00..=0xFE9F if self.gb.dma_active => 0xFF,
             0xFE00..=0xFE9F => self.gb.memory.oam[addr as usize - 0xFE00],
 
             0xFF00 => {
@@ -443,20 +1017,26 @@ impl GameState {
                 }
             }
 
+            0xFF01..=0xFF02 => self.serial.read(addr),
+
             0xFF04 => (self.gb.timer_registers.div >> 8) as u8,
 
             0xFF05 => self.gb.timer_registers.tima,
 
             0xFF06 => self.gb.timer_registers.tma,
 
-            0xFF07 => self.gb.timer_registers.tac,
+            0xFF07 => self.gb.timer_registers.tac | 0xF8,
+
+            // The top three bits aren't wired up and read as 1
+            0xFF0F => self.gb.i_flag | 0xE0,
 
-            0xFF0F => self.gb.i_flag,
+            0xFF10..=0xFF3F => self.apu.read(addr),
 
             // TODO IO Registers and other memory mapped stuff
             0xFF40 => self.gb.io_registers.lcdc,
 
-            0xFF41 => self.gb.io_registers.stat,
+            // Bit 7 isn't wired up
+            0xFF41 => self.gb.io_registers.stat | 0x80,
 
             0xFF42 => self.gb.io_registers.scy,
 
@@ -468,6 +1048,12 @@ impl GameState {
 
             0xFF46 => self.gb.dma,
 
+            0xFF47 => self.gb.io_registers.bgp,
+
+            0xFF48 => self.gb.io_registers.obp0,
+
+            0xFF49 => self.gb.io_registers.obp1,
+
             0xFF4A => self.gb.io_registers.wy,
 
             0xFF4B => self.gb.io_registers.wx,
@@ -482,7 +1068,16 @@ impl GameState {
 
     pub fn write(&mut self, value: u8, addr: u16) {
         match addr {
-            0x0000..=0x7FFF => (), // Read-Only!
+            0x0000..=0x7FFF => {
+                // MBC registers
+                let rumble = self.cart.rumble();
+                self.cart.write(value, addr);
+                if self.cart.rumble() != rumble
+                    && let Some(callback) = &mut self.rumble_callback
+                {
+                    callback(!rumble);
+                }
+            }
 
             0x8000..=0x9FFF => {
                 // println!(
@@ -492,15 +1087,13 @@ impl GameState {
                 self.gb.memory.vram[addr as usize - 0x8000] = value
             }
 
-            0xA000..=0xBFFF => {
-                // TODO External RAM
-                ()
-            }
+            0xA000..=0xBFFF => self.cart.write(value, addr),
 
             0xC000..=0xDFFF => self.gb.memory.wram[addr as usize - 0xC000] = value,
 
             0xE000..=0xFDFF => self.gb.memory.wram[addr as usize - 0xE000] = value,
 
+            0xFE00..=0xFE9F if self.gb.dma_active => (),
             0xFE00..=0xFE9F => self.gb.memory.oam[addr as usize - 0xFE00] = value,
 
             0xFF00 => {
@@ -508,36 +1101,90 @@ impl GameState {
                     (self.gb.io_registers.joyp & 0b1100_1111) | (value & 0b0011_0000);
             }
 
-            0xFF04 => self.gb.timer_registers.div = 0,
+            0xFF01..=0xFF02 => self.serial.write(value, addr),
 
-            0xFF05 => self.gb.timer_registers.tima = value,
+            0xFF04 => self.set_div(0),
 
-            0xFF06 => self.gb.timer_registers.tma = value,
+            // A write in the M-cycle after an overflow cancels the reload, but one in the same
+            // M-cycle as the reload loses out to it
+            0xFF05 => {
+                let timer = &mut self.gb.timer_registers;
+                if !timer.reloading {
+                    timer.tima = value;
+                    timer.overflowed = false;
+                }
+            }
+
+            0xFF06 => {
+                let timer = &mut self.gb.timer_registers;
+                timer.tma = value;
+                if timer.reloading {
+                    timer.tima = value;
+                }
+            }
 
+            // Switching the selected bit or disabling the timer while the signal is high is a
+            // falling edge too
             0xFF07 => {
-                // println!("Writing: 0b{:08b} to TAC", value);
-                self.gb.timer_registers.tac = value;
+                let timer = &mut self.gb.timer_registers;
+                let old_signal = timer.tima_signal();
+                timer.tac = value & 0b111;
+                if old_signal && !timer.tima_signal() {
+                    timer.increment_tima();
+                }
             }
 
             0xFF0F => self.gb.i_flag = value,
 
+            0xFF10..=0xFF3F => self.apu.write(value, addr),
+
             // TODO IO Registers and other memory mapped stuff
-            0xFF40 => self.gb.io_registers.lcdc = value,
+            0xFF40 => {
+                let was_on = self.gb.io_registers.lcdc & LCDC_ON != 0;
+                self.gb.io_registers.lcdc = value;
+                match (was_on, value & LCDC_ON != 0) {
+                    (true, false) => self.ppu.switch_off(&mut self.gb),
+                    (false, true) => self.ppu.switch_on(),
+                    _ => (),
+                }
+            }
 
-            0xFF41 => self.gb.io_registers.stat = value,
+            // The mode and LY=LYC bits are read-only. On the DMG the write also enables every
+            // source for a moment, so one made during HBlank, VBlank or LY=LYC raises the
+            // interrupt whatever value is written
+            0xFF41 => {
+                let read_only = self.gb.io_registers.stat & (STAT_MODE | STAT_LYC_EQUAL);
+                self.gb.io_registers.stat =
+                    read_only | STAT_HBLANK_INT | STAT_VBLANK_INT | STAT_LYC_INT;
+                self.gb.update_stat();
+                self.gb.io_registers.stat = read_only | (value & 0b0111_1000);
+                self.gb.update_stat();
+            }
 
             0xFF42 => self.gb.io_registers.scy = value,
 
             0xFF43 => self.gb.io_registers.scx = value,
 
-            0xFF45 => self.gb.io_registers.lyc = value,
+            0xFF45 => {
+                self.gb.io_registers.lyc = value;
+                self.gb.update_stat();
+            }
 
             0xFF46 => self.dma_oam(value),
 
+            0xFF47 => self.gb.io_registers.bgp = value,
+
+            0xFF48 => self.gb.io_registers.obp0 = value,
+
+            0xFF49 => self.gb.io_registers.obp1 = value,
+
             0xFF4A => self.gb.io_registers.wy = value,
 
             0xFF4B => self.gb.io_registers.wx = value,
 
+            // Unmapping the boot ROM is one-way until the next restart
+            0xFF50 if value != 0 => self.boot_rom_mapped = false,
+
             0xFF80..=0xFFFE => {
                 // println!("WRITING TO HRAM addr: 0x{:04X} value: 0x{:02X}", addr, value);
                 self.gb.memory.hram[addr as usize - 0xFF80] = value
@@ -548,124 +1195,161 @@ impl GameState {
         }
     }
 
-    pub fn set_interrupts(&mut self, on: bool) {
-        self.gb.ime = on;
+    // Bus accesses made by the CPU take one M-cycle each, and the rest of the machine is advanced
+    // through that cycle before the access lands
+    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.tick();
+        self.read(addr)
     }
 
-    pub fn get_interrupts(&self) -> bool {
-        self.gb.ime
+    pub fn cpu_write(&mut self, value: u8, addr: u16) {
+        self.tick();
+        self.write(value, addr);
     }
 
-    pub fn get_i_flag(&self) -> u8 {
-        self.gb.i_flag
+    // Advances everything but the CPU by one M-cycle
+    pub fn tick(&mut self) {
+        self.step_cycles += 1;
+        self.tick_timers();
+        self.step_dma();
+        if self.ppu.step(4, &mut self.gb) {
+            self.frame_ready = true;
+        }
     }
 
-    pub fn get_i_enable(&self) -> u8 {
-        self.gb.i_enable
+    pub fn begin_instruction(&mut self) {
+        self.step_cycles = 0;
     }
 
-    pub fn pc_moved(&mut self) -> bool {
-        self.gb.pc_moved
+    // Catches up on the internal M-cycles of an instruction that weren't spent on the bus
+    pub fn end_instruction(&mut self, m_cycles: u8) {
+        while self.step_cycles < m_cycles {
+            self.tick();
+        }
     }
 
-    pub fn set_pc_moved(&mut self, val: bool) {
-        self.gb.pc_moved = val;
+    fn tick_timers(&mut self) {
+        self.update_clock(4);
+        let timer = &mut self.gb.timer_registers;
+        timer.reloading = false;
+        if timer.overflowed {
+            timer.overflowed = false;
+            timer.reloading = true;
+            timer.tima = timer.tma;
+            self.gb.request_interrupt(INT_TIMER);
+        }
+        self.set_div(self.gb.timer_registers.div.wrapping_add(4));
+        self.apu.step(4);
     }
 
-    pub fn update_clock(&mut self, add_cycles: u8) {
-        self.gb.cycles += add_cycles as u128;
+    // Every change to the counter goes through here, so resetting it can clock TIMA and the
+    // frame sequencer just like counting past their bits does
+    fn set_div(&mut self, div: u16) {
+        let timer = &mut self.gb.timer_registers;
+        let old_div = timer.div;
+        let old_signal = timer.tima_signal();
+        timer.div = div;
+        if old_signal && !timer.tima_signal() {
+            timer.increment_tima();
+        }
+        if old_div & DIV_APU_BIT != 0 && div & DIV_APU_BIT == 0 {
+            self.apu.clock_frame_sequencer();
+        }
+        if old_div & DIV_SERIAL_BIT != 0 && div & DIV_SERIAL_BIT == 0 && self.serial.clock() {
+            self.gb.request_interrupt(INT_SERIAL);
+        }
     }
 
-    pub fn inc_div(&mut self, amount: u8) {
-        self.gb.timer_registers.div = self.gb.timer_registers.div.wrapping_add(amount as u16);
+    // True once per frame, when the PPU enters VBlank
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
     }
 
-    pub fn get_tac(&self) -> u8 {
-        self.gb.timer_registers.tac
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.ppu.current_fb
     }
 
-    pub fn inc_available_cycles(&mut self, val: u16) {
-        assert!(self.gb.available_cycles <= 0xFFFF - val);
-        self.gb.available_cycles += val;
+    pub fn set_shades(&mut self, shades: [u32; 4]) {
+        self.ppu.set_shades(shades);
     }
 
-    pub fn dec_available_cycles(&mut self, val: u16) {
-        assert!(self.gb.available_cycles >= val);
-        self.gb.available_cycles -= val;
+    pub fn set_interrupts(&mut self, on: bool) {
+        self.gb.ime = on;
     }
 
-    pub fn get_available_cycles(&self) -> u16 {
-        self.gb.available_cycles
+    pub fn get_interrupts(&self) -> bool {
+        self.gb.ime
     }
 
-    pub fn update_tima(&mut self) {
-        if self.gb.timer_registers.tima > 0xFF - 1 {
-            self.gb.timer_registers.tima = self.gb.timer_registers.tma;
-            // Timer Interrupt
-            self.write(self.read(0xFF0F) | INT_TIMER, 0xFF0F);
-        } else {
-            self.gb.timer_registers.tima += 1;
-        }
+    pub fn ei_pending(&self) -> bool {
+        self.gb.ei_pending
     }
 
-    pub fn get_oam_entry(&self, loc: u8) -> [u8; 4] {
-        let l = (loc % 0xA0) as usize;
-        return [
-            self.gb.memory.oam[l],
-            self.gb.memory.oam[l + 1],
-            self.gb.memory.oam[l + 2],
-            self.gb.memory.oam[l + 3],
-        ];
+    pub fn set_ei_pending(&mut self, val: bool) {
+        self.gb.ei_pending = val;
     }
 
-    pub fn get_lcdc(&self) -> u8 {
-        return self.gb.io_registers.lcdc;
+    pub fn get_i_flag(&self) -> u8 {
+        self.gb.i_flag
     }
 
-    pub fn get_ly(&self) -> u8 {
-        return self.gb.io_registers.ly;
+    pub fn get_i_enable(&self) -> u8 {
+        self.gb.i_enable
     }
 
-    pub fn get_scx(&self) -> u8 {
-        return self.gb.io_registers.scx;
+    pub fn pc_moved(&mut self) -> bool {
+        self.gb.pc_moved
     }
 
-    pub fn get_scy(&self) -> u8 {
-        return self.gb.io_registers.scy;
+    pub fn set_pc_moved(&mut self, val: bool) {
+        self.gb.pc_moved = val;
     }
 
-    pub fn get_wx(&self) -> u8 {
-        return self.gb.io_registers.wx;
+    // Any interrupt both requested and enabled, regardless of IME
+    pub fn interrupt_pending(&self) -> bool {
+        self.gb.i_flag & self.gb.i_enable & 0x1F != 0
     }
 
-    pub fn get_wy(&self) -> u8 {
-        return self.gb.io_registers.wy;
+    pub fn halted(&self) -> bool {
+        self.gb.halted
     }
 
-    pub fn inc_ly(&mut self, amount: u8) {
-        self.gb.io_registers.ly = (self.gb.io_registers.ly + amount) % 154;
+    pub fn set_halted(&mut self, val: bool) {
+        self.gb.halted = val;
     }
 
-    pub fn set_ly(&mut self, val: u8) {
-        self.gb.io_registers.ly = val;
+    pub fn halt_bug(&self) -> bool {
+        self.gb.halt_bug
     }
 
-    pub fn get_tile_index(&self, tile_in_map: u16) -> u8 {
-        if self.gb.io_registers.lcdc & LCDC_TILE_MAP == 0 {
-            return self.read(0x9800 + tile_in_map);
-        } else {
-            return self.read(0x9C00 + tile_in_map);
-        }
+    pub fn set_halt_bug(&mut self, val: bool) {
+        self.gb.halt_bug = val;
     }
 
-    pub fn get_tile_from_addr(&self, addr: u16) -> [u16; 8] {
-        let mut result = [0u16; 8];
-        for i in 0..8 {
-            let byte1 = self.gb.memory.vram[(addr + 2 * i) as usize - 0x8000];
-            let byte2 = self.gb.memory.vram[(addr + 2 * i + 1) as usize - 0x8000];
-            result[i as usize] = ((byte2 as u16) << 8) | (byte1 as u16);
-        }
-        result
+    pub fn stopped(&self) -> bool {
+        self.gb.stopped
+    }
+
+    pub fn set_stopped(&mut self, val: bool) {
+        self.gb.stopped = val;
+    }
+
+    // True when a pressed button is on a row selected in P1
+    pub fn joypad_line_low(&self) -> bool {
+        self.read(0xFF00) & 0x0F != 0x0F
+    }
+
+    fn update_clock(&mut self, add_cycles: u8) {
+        self.gb.cycles += add_cycles as u128;
+    }
+
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    // Interleaved stereo samples in -1.0..=1.0 generated since the last call
+    pub fn drain_audio_samples(&mut self) -> Vec<f32> {
+        self.apu.drain_samples()
     }
 
     pub fn update_joypad(
@@ -679,6 +1363,7 @@ impl GameState {
         left: bool,
         right: bool,
     ) {
+        let lines = self.read(0xFF00) & 0x0F;
         self.gb.joypad.a_button = a;
         self.gb.joypad.b_button = b;
         self.gb.joypad.start_button = start;
@@ -687,6 +1372,11 @@ impl GameState {
         self.gb.joypad.down_button = down;
         self.gb.joypad.left_button = left;
         self.gb.joypad.right_button = right;
+
+        // Any selected line going from high to low requests the joypad interrupt
+        if lines & !self.read(0xFF00) & 0x0F != 0 {
+            self.gb.i_flag |= INT_JOYPAD;
+        }
     }
 
     // pub fn print_oam(&self) {
@@ -696,3 +1386,44 @@ impl GameState {
     //     println!();
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(game_state: &mut GameState) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        game_state.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    #[test]
+    fn a_truncated_state_leaves_the_machine_alone() {
+        // MBC1+RAM+BATTERY
+        let mut game_state =
+            GameState::from_rom(CartridgeHeader::test_rom(0x03, 0x02, &[])).unwrap();
+        game_state.write(0x0A, 0x0000);
+        game_state.write(0x12, 0xA000);
+        let saved = snapshot(&mut game_state);
+
+        game_state.write(0x34, 0xA000);
+        game_state.write(0x56, 0xC000);
+        game_state.set_register16(Register::PC, 0x1234);
+        let before = snapshot(&mut game_state);
+        for len in (0..saved.len()).step_by(61).chain([saved.len() - 1]) {
+            assert!(game_state
+                .load_state(&mut StateReader::new(&saved[..len]))
+                .is_err());
+            assert!(
+                snapshot(&mut game_state) == before,
+                "cut at {len} changed the state"
+            );
+        }
+
+        game_state
+            .load_state(&mut StateReader::new(&saved))
+            .unwrap();
+        assert_eq!(game_state.read(0xA000), 0x12);
+        assert_eq!(game_state.get_register16(Register::PC), 0x0100);
+    }
+}
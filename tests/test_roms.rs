@@ -0,0 +1,313 @@
+// Runs Blargg's and mooneye's test ROMs headlessly and checks their verdicts. The ROMs aren't
+// redistributable, so they're looked for under tests/roms, or wherever GB_TEST_ROMS points,
+// laid out as:
+//
+//   blargg/    a checkout of https://github.com/retrio/gb-test-roms
+//   mooneye/   an extracted mooneye-test-suite build, with acceptance/ inside it
+//
+// The test is ignored by default since it needs them, `cargo test -- --ignored` runs it, and any
+// ROM that can't be found fails the run rather than letting it pass without testing. A ROM that
+// only shows its result on screen can be checked by putting the expected framebuffer hash, as
+// printed in the table, in a .hash file next to it.
+//
+// ROMs that are expected to fail are listed in tests/known_failures.txt. Running with
+// GB_TEST_ROMS_BLESS=1 rewrites it from the results, so it only ever holds what a run saw fail
+use my_emulator::{Emulator, SerialLink};
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const CYCLES_PER_SECOND: u64 = 4194304;
+const CYCLES_PER_FRAME: u64 = 70224;
+
+// How long each suite is given before it's reported as timing out, in emulated seconds
+const BLARGG_ROMS: [(&str, u64); 5] = [
+    ("blargg/cpu_instrs/cpu_instrs.gb", 70),
+    ("blargg/instr_timing/instr_timing.gb", 5),
+    ("blargg/mem_timing/mem_timing.gb", 10),
+    ("blargg/mem_timing-2/mem_timing.gb", 10),
+    ("blargg/halt_bug.gb", 10),
+];
+const MOONEYE_DIR: &str = "mooneye/acceptance";
+const MOONEYE_SECONDS: u64 = 20;
+
+// ROMs, relative to the ROM directory, that fail until the hardware they test is emulated, one
+// per line. Anything else failing is a regression
+const KNOWN_FAILURES: &str = "tests/known_failures.txt";
+const KNOWN_FAILURES_HEADER: &str = "\
+# ROMs, relative to the test ROM directory, that fail until the hardware they test is emulated.
+# Written from an actual run by `GB_TEST_ROMS_BLESS=1 cargo test --release -- --ignored`
+";
+
+// Mooneye passes by loading the Fibonacci numbers into B-L, and fails with 0x42 in all of them
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL: [u8; 6] = [0x42; 6];
+const LD_B_B: u8 = 0x40;
+
+// Blargg's newer ROMs also report through cartridge RAM: a signature, then a status byte that
+// stays 0x80 while the test runs and text after it
+const BLARGG_STATUS: u16 = 0xA000;
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_RUNNING: u8 = 0x80;
+const BLARGG_TEXT: u16 = 0xA004;
+
+#[derive(Clone, Copy)]
+enum Protocol {
+    Blargg,
+    Mooneye,
+}
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Timeout(u64),
+    Missing,
+}
+
+// Keeps whatever the ROM sends over the link cable
+struct SerialCapture(Rc<RefCell<Vec<u8>>>);
+
+impl SerialLink for SerialCapture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.0.borrow_mut().push(byte);
+        0xFF
+    }
+
+    fn receive(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+}
+
+fn known_failures_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(KNOWN_FAILURES)
+}
+
+fn known_failures() -> Vec<String> {
+    fs::read_to_string(known_failures_path())
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+fn rom_dir() -> PathBuf {
+    env::var_os("GB_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms"))
+}
+
+// FNV-1a over the pixels
+fn screen_hash(emulator: &Emulator) -> u64 {
+    emulator
+        .framebuffer()
+        .iter()
+        .fold(0xCBF29CE484222325, |hash, &pixel| {
+            (hash ^ pixel as u64).wrapping_mul(0x100000001B3)
+        })
+}
+
+fn blargg_verdict(emulator: &Emulator, serial: &[u8]) -> Option<Outcome> {
+    let text = String::from_utf8_lossy(serial);
+    if text.contains("Passed") {
+        return Some(Outcome::Pass);
+    }
+    if text.contains("Failed") {
+        return Some(Outcome::Fail(text.trim().replace('\n', " ")));
+    }
+
+    let signature = [1, 2, 3].map(|i| emulator.peek(BLARGG_STATUS + i));
+    let status = emulator.peek(BLARGG_STATUS);
+    if signature != BLARGG_SIGNATURE || status == BLARGG_RUNNING {
+        return None;
+    }
+    if status == 0 {
+        return Some(Outcome::Pass);
+    }
+    let text: String = (BLARGG_TEXT..0xC000)
+        .map(|addr| emulator.peek(addr))
+        .take_while(|&b| b != 0)
+        .map(char::from)
+        .collect();
+    Some(Outcome::Fail(format!(
+        "status {status}: {}",
+        text.trim().replace('\n', " ")
+    )))
+}
+
+fn mooneye_verdict(signature: [u8; 6]) -> Option<Outcome> {
+    if signature == MOONEYE_PASS {
+        Some(Outcome::Pass)
+    } else if signature == MOONEYE_FAIL {
+        Some(Outcome::Fail("failure signature".to_string()))
+    } else {
+        None
+    }
+}
+
+fn run_rom(path: &Path, protocol: Protocol, seconds: u64) -> Outcome {
+    let Ok(rom) = fs::read(path) else {
+        return Outcome::Missing;
+    };
+    let mut emulator = match Emulator::load_rom(rom) {
+        Ok(emulator) => emulator,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let serial = Rc::new(RefCell::new(Vec::new()));
+    emulator.set_serial_link(Box::new(SerialCapture(serial.clone())));
+    let expected_hash = fs::read_to_string(path.with_extension("hash"))
+        .ok()
+        .and_then(|hash| u64::from_str_radix(hash.trim(), 16).ok());
+
+    let mut cycles = 0;
+    let mut next_check = CYCLES_PER_FRAME;
+    while cycles < seconds * CYCLES_PER_SECOND {
+        if let Protocol::Mooneye = protocol {
+            let regs = emulator.registers();
+            if emulator.peek(regs.pc) == LD_B_B {
+                let signature = [regs.b, regs.c, regs.d, regs.e, regs.h, regs.l];
+                if let Some(outcome) = mooneye_verdict(signature) {
+                    return outcome;
+                }
+            }
+        }
+        cycles += emulator.step_instruction() as u64;
+
+        if cycles < next_check {
+            continue;
+        }
+        next_check += CYCLES_PER_FRAME;
+        let verdict = match protocol {
+            Protocol::Blargg => blargg_verdict(&emulator, &serial.borrow()),
+            // Newer mooneye builds send the signature over serial too
+            Protocol::Mooneye => serial
+                .borrow()
+                .get(..6)
+                .and_then(|bytes| mooneye_verdict(bytes.try_into().unwrap())),
+        };
+        if let Some(outcome) = verdict {
+            return outcome;
+        }
+        if expected_hash.is_some_and(|hash| hash == screen_hash(&emulator)) {
+            return Outcome::Pass;
+        }
+    }
+    Outcome::Timeout(screen_hash(&emulator))
+}
+
+// Mooneye puts the models a test is for after a dash, G being the DMG and dmgABC its later
+// revisions. Tests without one run everywhere
+fn runs_on_dmg(path: &Path) -> bool {
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    match name.rsplit_once('-') {
+        Some((_, models)) => models.contains('G') || models.contains("dmgABC"),
+        None => true,
+    }
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension().is_some_and(|ext| ext == "gb") && runs_on_dmg(&path) {
+            roms.push(path);
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs the test ROMs, see tests/test_roms.rs"]
+fn test_roms() {
+    let dir = rom_dir();
+    assert!(
+        dir.is_dir(),
+        "no test ROMs at {}, put them there or point GB_TEST_ROMS at them",
+        dir.display()
+    );
+    let mut runs: Vec<(PathBuf, Protocol, u64)> = BLARGG_ROMS
+        .iter()
+        .map(|&(rom, seconds)| (dir.join(rom), Protocol::Blargg, seconds))
+        .collect();
+    let mut mooneye = Vec::new();
+    find_roms(&dir.join(MOONEYE_DIR), &mut mooneye);
+    if mooneye.is_empty() {
+        runs.push((dir.join(MOONEYE_DIR), Protocol::Mooneye, 0));
+    }
+    runs.extend(
+        mooneye
+            .into_iter()
+            .map(|rom| (rom, Protocol::Mooneye, MOONEYE_SECONDS)),
+    );
+
+    let known_failures = known_failures();
+    let mut failing = Vec::new();
+    let mut regressions = Vec::new();
+    let mut missing = Vec::new();
+    let mut fixed = Vec::new();
+    let mut passed = 0;
+    println!("{:<60} RESULT", "ROM");
+    for (path, protocol, seconds) in runs {
+        let name = path.strip_prefix(&dir).unwrap_or(&path).to_string_lossy();
+        let outcome = run_rom(&path, protocol, seconds);
+        let known_failure = known_failures.iter().any(|known| *known == name);
+        let result = match &outcome {
+            Outcome::Pass => "pass".to_string(),
+            Outcome::Fail(reason) => format!("FAIL {reason}"),
+            Outcome::Timeout(hash) => format!("TIMEOUT after {seconds}s, screen {hash:016x}"),
+            Outcome::Missing => "MISSING".to_string(),
+        };
+        println!("{name:<60} {result}");
+
+        match outcome {
+            Outcome::Pass => {
+                passed += 1;
+                if known_failure {
+                    fixed.push(name.to_string());
+                }
+            }
+            Outcome::Fail(_) | Outcome::Timeout(_) => {
+                failing.push(name.to_string());
+                if !known_failure {
+                    regressions.push(name.to_string());
+                }
+            }
+            Outcome::Missing => missing.push(name.to_string()),
+        }
+    }
+
+    println!(
+        "{passed} passed, {} failed, {} of them known",
+        failing.len(),
+        failing.len() - regressions.len()
+    );
+    assert!(missing.is_empty(), "not found: {}", missing.join(", "));
+    if env::var_os("GB_TEST_ROMS_BLESS").is_some() {
+        let list: String = failing.iter().map(|name| format!("{name}\n")).collect();
+        fs::write(
+            known_failures_path(),
+            KNOWN_FAILURES_HEADER.to_string() + &list,
+        )
+        .unwrap();
+        println!("Wrote {} ROMs to {KNOWN_FAILURES}", failing.len());
+        return;
+    }
+    if !fixed.is_empty() {
+        println!(
+            "Now passing, rebless {KNOWN_FAILURES} to drop them: {}",
+            fixed.join(", ")
+        );
+    }
+    assert!(
+        regressions.is_empty(),
+        "failing: {}",
+        regressions.join(", ")
+    );
+}